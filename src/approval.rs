@@ -0,0 +1,265 @@
+use crate::*;
+use near_contract_standards::non_fungible_token::approval::NonFungibleTokenApproval;
+use near_sdk::Gas;
+
+const GAS_FOR_NFT_APPROVE: Gas = Gas(10_000_000_000_000);
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ApprovalConstraints {
+    /// Approval the constraints were set for, re-approving the account drops them.
+    pub approval_id: u64,
+    pub expires_at: Option<U64>,
+    pub min_price: Option<U128>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ApprovalInfo {
+    pub account_id: AccountId,
+    pub approval_id: u64,
+    pub expires_at: Option<U64>,
+    pub min_price: Option<U128>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenApprovals {
+    pub token_id: TokenId,
+    pub approvals: Vec<ApprovalInfo>,
+}
+
+#[near_bindgen]
+impl NonFungibleTokenApproval for Contract {
+    #[payable]
+    fn nft_approve(
+        &mut self,
+        token_id: TokenId,
+        account_id: AccountId,
+        msg: Option<String>,
+    ) -> Option<Promise> {
+        self.assert_token_transferable(&token_id);
+        self.approval_constraints.remove(&(token_id.clone(), account_id.clone()));
+        self.tokens.nft_approve(token_id, account_id, msg)
+    }
+
+    #[payable]
+    fn nft_revoke(&mut self, token_id: TokenId, account_id: AccountId) {
+        self.approval_constraints.remove(&(token_id.clone(), account_id.clone()));
+        self.tokens.nft_revoke(token_id, account_id)
+    }
+
+    #[payable]
+    fn nft_revoke_all(&mut self, token_id: TokenId) {
+        self.internal_remove_approval_constraints(&token_id);
+        self.tokens.nft_revoke_all(token_id)
+    }
+
+    fn nft_is_approved(
+        &self,
+        token_id: TokenId,
+        approved_account_id: AccountId,
+        approval_id: Option<u64>,
+    ) -> bool {
+        let is_expired = self
+            .internal_approval_constraints(&token_id, &approved_account_id)
+            .and_then(|constraints| constraints.expires_at)
+            .map(|expires_at| env::block_timestamp() >= expires_at.0)
+            .unwrap_or(false);
+        !is_expired && self.tokens.nft_is_approved(token_id, approved_account_id, approval_id)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Approves `account_id` like `nft_approve`, but the approval stops working at
+    /// `expires_at`. With `min_price` the token can only be sold by `nft_transfer_payout`
+    /// for at least that price. Storage of the approval and its constraints is paid
+    /// from the attached deposit.
+    #[payable]
+    pub fn nft_approve_with_constraints(&mut self,
+                                        token_id: TokenId,
+                                        account_id: AccountId,
+                                        msg: Option<String>,
+                                        expires_at: Option<U64>,
+                                        min_price: Option<U128>) -> Option<Promise> {
+        if let Some(expires_at) = expires_at {
+            assert!(expires_at.0 > env::block_timestamp(), "Approval expiration must be in the future");
+        }
+        assert_at_least_one_yocto();
+        self.assert_token_transferable(&token_id);
+        let owner_id = self
+            .tokens.owner_by_id
+            .get(&token_id)
+            .expect("Error: no such token id.");
+        assert_eq!(env::predecessor_account_id(), owner_id, "Predecessor must be token owner.");
+
+        // approval is stored here instead of `nft_approve`, so its storage and the
+        // constraints are paid from one deposit
+        let initial_storage_usage = env::storage_usage();
+        let approval_id = self
+            .tokens
+            .next_approval_id_by_id
+            .as_ref()
+            .and_then(|by_id| by_id.get(&token_id))
+            .unwrap_or(1);
+        let mut approvals = self.internal_token_approvals(&token_id);
+        approvals.insert(account_id.clone(), approval_id);
+        if let Some(approvals_by_id) = &mut self.tokens.approvals_by_id {
+            approvals_by_id.insert(&token_id, &approvals);
+        }
+        if let Some(next_approval_id_by_id) = &mut self.tokens.next_approval_id_by_id {
+            next_approval_id_by_id.insert(&token_id, &(approval_id + 1));
+        }
+        self.approval_constraints.insert(&(token_id.clone(), account_id.clone()), &ApprovalConstraints {
+            approval_id,
+            expires_at,
+            min_price,
+        });
+        refund_storage_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+
+        msg.map(|msg| Promise::new(account_id)
+            .function_call(
+                "nft_on_approve".to_string(),
+                json!({
+                    "token_id": token_id,
+                    "owner_id": owner_id,
+                    "approval_id": approval_id,
+                    "msg": msg
+                }).to_string().into_bytes(),
+                0,
+                env::prepaid_gas() - GAS_FOR_NFT_APPROVE))
+    }
+
+    pub fn nft_approvals_for_owner(&self, account_id: AccountId, from: u64, limit: u64) -> Vec<TokenApprovals> {
+        let token_ids = match self
+            .tokens
+            .tokens_per_owner
+            .as_ref()
+            .and_then(|by_owner| by_owner.get(&account_id)) {
+            Some(token_ids) => token_ids,
+            None => return vec![],
+        };
+        token_ids
+            .iter()
+            .skip(from as usize)
+            .take(limit as usize)
+            .map(|token_id| TokenApprovals {
+                approvals: self
+                    .internal_token_approvals(&token_id)
+                    .into_iter()
+                    .map(|(account_id, approval_id)| {
+                        let constraints = self.internal_approval_constraints(&token_id, &account_id);
+                        ApprovalInfo {
+                            approval_id,
+                            expires_at: constraints.as_ref().and_then(|c| c.expires_at),
+                            min_price: constraints.as_ref().and_then(|c| c.min_price),
+                            account_id,
+                        }
+                    })
+                    .collect(),
+                token_id,
+            })
+            .collect()
+    }
+
+    /// Revokes all approvals of the caller's tokens in range `[from, from + limit)`
+    /// and refunds released storage in one transfer. Returns number of processed tokens.
+    #[payable]
+    pub fn nft_revoke_all_for_owner(&mut self, from: u64, limit: u64) -> u64 {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        let token_ids: Vec<TokenId> = self
+            .tokens
+            .tokens_per_owner
+            .as_ref()
+            .and_then(|by_owner| by_owner.get(&owner_id))
+            .map(|token_ids| token_ids
+                .iter()
+                .skip(from as usize)
+                .take(limit as usize)
+                .collect())
+            .unwrap_or_default();
+
+        let initial_storage_usage = env::storage_usage();
+        for token_id in token_ids.iter() {
+            self.internal_remove_approval_constraints(token_id);
+            if let Some(approvals_by_id) = &mut self.tokens.approvals_by_id {
+                approvals_by_id.remove(token_id);
+            }
+        }
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        if storage_released > 0 {
+            Promise::new(owner_id.clone()).transfer(env::storage_byte_cost() * storage_released as u128);
+        }
+
+        env::log_str(&json!({
+            "type": "nft_revoke_all_for_owner",
+            "data": {
+                "owner_id": owner_id,
+                "token_ids": token_ids
+            }
+        }).to_string());
+
+        token_ids.len() as u64
+    }
+
+    /// Checks expiration and price constraints of the caller's approval. Does
+    /// nothing when the token owner moves the token. An approval with `min_price`
+    /// can only be used by `nft_transfer_payout`, which passes the sale price.
+    pub(crate) fn assert_approval_constraints(&self, token_id: &TokenId, price: Option<U128>) {
+        let sender_id = env::predecessor_account_id();
+        if self.tokens.owner_by_id.get(token_id) == Some(sender_id.clone()) {
+            return;
+        }
+        if let Some(constraints) = self.internal_approval_constraints(token_id, &sender_id) {
+            if let Some(expires_at) = constraints.expires_at {
+                assert!(env::block_timestamp() < expires_at.0, "Approval has expired");
+            }
+            if let Some(min_price) = constraints.min_price {
+                let price = price.expect("Approval has a minimal price, use nft_transfer_payout");
+                assert!(price.0 >= min_price.0, "Price is lower than allowed by the approval");
+            }
+        }
+    }
+
+    /// Constraints of the current approval of the account, stale ones are ignored.
+    fn internal_approval_constraints(&self, token_id: &TokenId, account_id: &AccountId) -> Option<ApprovalConstraints> {
+        let approval_id = self.internal_token_approvals(token_id).get(account_id).cloned()?;
+        self
+            .approval_constraints
+            .get(&(token_id.clone(), account_id.clone()))
+            .filter(|constraints| constraints.approval_id == approval_id)
+    }
+
+    /// Drops constraints of approvals of `account_ids`, which a transfer clears, and
+    /// refunds their storage to `owner_id` who paid for it.
+    pub(crate) fn internal_refund_approval_constraints(&mut self,
+                                                       token_id: &TokenId,
+                                                       owner_id: &AccountId,
+                                                       account_ids: Vec<AccountId>) {
+        let initial_storage_usage = env::storage_usage();
+        for account_id in account_ids {
+            self.approval_constraints.remove(&(token_id.clone(), account_id));
+        }
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        if storage_released > 0 {
+            Promise::new(owner_id.clone()).transfer(env::storage_byte_cost() * storage_released as u128);
+        }
+    }
+
+    pub(crate) fn internal_remove_approval_constraints(&mut self, token_id: &TokenId) {
+        for account_id in self.internal_token_approvals(token_id).keys() {
+            self.approval_constraints.remove(&(token_id.clone(), account_id.clone()));
+        }
+    }
+
+    pub(crate) fn internal_token_approvals(&self, token_id: &TokenId) -> HashMap<AccountId, u64> {
+        self
+            .tokens
+            .approvals_by_id
+            .as_ref()
+            .and_then(|by_id| by_id.get(token_id))
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,211 @@
+use crate::*;
+
+/// Bids placed within this window before the end move the end further by the same window.
+const AUCTION_EXTENSION: u64 = 10 * 60 * 1_000_000_000;
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Bid {
+    pub bidder_id: AccountId,
+    pub amount: U128,
+    pub placed_at: U64,
+}
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Auction {
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+    pub collection_id: Option<CollectionId>,
+    pub reserve_price: U128,
+    pub end_at: U64,
+    pub highest_bid: Option<Bid>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Starts an English auction of own token which ends at `end_at` (nanoseconds).
+    /// Storage of the auction is paid from the attached deposit.
+    #[payable]
+    pub fn create_auction(&mut self, token_id: TokenId, reserve_price: U128, end_at: U64) -> Auction {
+        let owner_id = env::predecessor_account_id();
+        assert_eq!(self.tokens.owner_by_id.get(&token_id),
+                   Some(owner_id.clone()),
+                   "Only owner of token can auction it");
+        assert!(reserve_price.0 > 0, "Reserve price must be positive");
+        assert!(end_at.0 > env::block_timestamp(), "Auction must end in the future");
+        assert!(self.auctions.get(&token_id).is_none(), "Token is already on auction");
+        assert!(self.listings.get(&token_id).is_none(), "Token is listed, delist it first");
+        self.assert_token_transferable(&token_id);
+        self.assert_token_unlocked(&token_id);
+
+        let initial_storage_usage = env::storage_usage();
+        let auction = Auction {
+            token_id: token_id.clone(),
+            owner_id,
+            collection_id: self.internal_token_collection_id(&token_id),
+            reserve_price,
+            end_at,
+            highest_bid: None,
+        };
+        self.auctions.insert(&token_id, &auction);
+        if let Some(collection_id) = &auction.collection_id {
+            let mut token_ids = self
+                .auctions_by_collection
+                .get(collection_id)
+                .unwrap_or_else(||
+                    UnorderedSet::new(StorageKey::AuctionsByCollectionInner {
+                        collection_id_hash: hash_string(collection_id)
+                    }.try_to_vec().unwrap()));
+            token_ids.insert(&token_id);
+            self.auctions_by_collection.insert(collection_id, &token_ids);
+        }
+        refund_storage_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+
+        env::log_str(&json!({
+            "type": "create_auction",
+            "data": auction.clone()
+        }).to_string());
+
+        auction
+    }
+
+    /// Bids the attached deposit. The bid has to reach the reserve price and exceed
+    /// the current highest bid, which is refunded to its bidder.
+    #[payable]
+    pub fn place_bid(&mut self, token_id: TokenId) -> Auction {
+        let mut auction = self.auctions.get(&token_id).expect("Error: token is not on auction.");
+        let bidder_id = env::predecessor_account_id();
+        let amount = env::attached_deposit();
+        let now = env::block_timestamp();
+        assert!(now < auction.end_at.0, "Auction has ended");
+        assert_ne!(auction.owner_id, bidder_id, "Owner can't bid on own token");
+        assert!(amount >= auction.reserve_price.0,
+                "Bid must be at least reserve price {}", auction.reserve_price.0);
+        if let Some(previous_bid) = &auction.highest_bid {
+            assert!(amount > previous_bid.amount.0,
+                    "Bid must be higher than {}", previous_bid.amount.0);
+            Promise::new(previous_bid.bidder_id.clone()).transfer(previous_bid.amount.0);
+        }
+
+        auction.highest_bid = Some(Bid {
+            bidder_id,
+            amount: U128(amount),
+            placed_at: U64(now),
+        });
+        if auction.end_at.0 - now < AUCTION_EXTENSION {
+            auction.end_at = U64(now + AUCTION_EXTENSION);
+        }
+        self.auctions.insert(&token_id, &auction);
+
+        env::log_str(&json!({
+            "type": "place_bid",
+            "data": {
+                "token_id": token_id,
+                "bid": auction.highest_bid,
+                "end_at": auction.end_at
+            }
+        }).to_string());
+
+        auction
+    }
+
+    /// Owner can cancel the auction until the first bid is placed.
+    #[payable]
+    pub fn cancel_auction(&mut self, token_id: TokenId) {
+        assert_one_yocto();
+        let auction = self.auctions.get(&token_id).expect("Error: token is not on auction.");
+        assert_eq!(auction.owner_id, env::predecessor_account_id(), "Only owner of token can cancel auction");
+        assert!(auction.highest_bid.is_none(), "Auction with bids can't be cancelled");
+
+        let initial_storage_usage = env::storage_usage();
+        self.internal_remove_auction(&token_id);
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        if storage_released > 0 {
+            Promise::new(auction.owner_id.clone()).transfer(env::storage_byte_cost() * storage_released as u128);
+        }
+
+        env::log_str(&json!({
+            "type": "cancel_auction",
+            "data": {
+                "token_id": token_id,
+                "owner_id": auction.owner_id
+            }
+        }).to_string());
+    }
+
+    /// Anyone can settle an ended auction. The token goes to the highest bidder and
+    /// the bid is paid out according to `nft_payout`.
+    pub fn settle_auction(&mut self, token_id: TokenId) -> Option<Payout> {
+        let auction = self.auctions.get(&token_id).expect("Error: token is not on auction.");
+        assert!(env::block_timestamp() >= auction.end_at.0, "Auction hasn't ended yet");
+
+        self.internal_remove_auction(&token_id);
+        let payout = auction.highest_bid.as_ref().map(|bid|
+            self.internal_sell_token(&token_id, &auction.owner_id, &bid.bidder_id, bid.amount, None));
+
+        env::log_str(&json!({
+            "type": "settle_auction",
+            "data": {
+                "token_id": token_id,
+                "seller_id": auction.owner_id,
+                "buyer_id": auction.highest_bid.as_ref().map(|bid| bid.bidder_id.clone()),
+                "price": auction.highest_bid.as_ref().map(|bid| bid.amount),
+                "collection_id": auction.collection_id
+            }
+        }).to_string());
+
+        payout
+    }
+
+    pub fn get_auction(&self, token_id: TokenId) -> Option<Auction> {
+        self.auctions.get(&token_id)
+    }
+
+    /// Auctions which weren't settled or cancelled yet.
+    pub fn get_auctions(&self, from: u64, limit: u64) -> Vec<Auction> {
+        let to = std::cmp::min(from.saturating_add(limit), self.auctions.len());
+        (from..to)
+            .filter_map(|i| self.auctions.values_as_vector().get(i))
+            .collect()
+    }
+
+    pub fn get_auctions_by_collection(&self, collection_id: CollectionId, from: u64, limit: u64) -> Vec<Auction> {
+        self
+            .auctions_by_collection
+            .get(&collection_id)
+            .map(|token_ids| token_ids
+                .iter()
+                .skip(from as usize)
+                .take(limit as usize)
+                .filter_map(|token_id| self.auctions.get(&token_id))
+                .collect())
+            .unwrap_or_default()
+    }
+
+    /// Tokens with bids can only leave their owner by `settle_auction`.
+    pub(crate) fn assert_token_has_no_bids(&self, token_id: &TokenId) {
+        assert!(self.auctions.get(token_id).and_then(|auction| auction.highest_bid).is_none(),
+                "Token is on auction with bids");
+    }
+
+    /// Drops the auction of a token which left its owner, refunding the highest bid.
+    pub(crate) fn internal_cancel_auction(&mut self, token_id: &TokenId) {
+        if let Some(auction) = self.internal_remove_auction(token_id) {
+            if let Some(bid) = auction.highest_bid {
+                Promise::new(bid.bidder_id).transfer(bid.amount.0);
+            }
+        }
+    }
+
+    fn internal_remove_auction(&mut self, token_id: &TokenId) -> Option<Auction> {
+        let auction = self.auctions.remove(token_id)?;
+        if let Some(collection_id) = &auction.collection_id {
+            if let Some(mut token_ids) = self.auctions_by_collection.get(collection_id) {
+                token_ids.remove(token_id);
+                self.auctions_by_collection.insert(collection_id, &token_ids);
+            }
+        }
+        Some(auction)
+    }
+}
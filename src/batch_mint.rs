@@ -0,0 +1,151 @@
+use crate::*;
+
+/// Upper bound of tokens minted by one `nft_batch_mint` call.
+const MAX_BATCH_MINT_SIZE: usize = 50;
+/// Rough gas cost of minting a single token with metadata, enumeration and royalties.
+pub(crate) const GAS_FOR_MINT: u64 = 5_000_000_000_000;
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BatchMintItem {
+    pub token_owner_id: AccountId,
+    pub token_metadata: TokenMetadata,
+    pub payout: Option<Payout>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Mints one token per item to the collection. Storage of the whole batch
+    /// is paid from the attached deposit, the rest is refunded to the caller.
+    #[payable]
+    pub fn nft_batch_mint(&mut self, collection_id: CollectionId, items: Vec<BatchMintItem>) -> Vec<TokenId> {
+        assert!(!items.is_empty(), "Nothing to mint");
+        assert!(items.len() <= MAX_BATCH_MINT_SIZE,
+                "Can't mint more than {} tokens in one batch", MAX_BATCH_MINT_SIZE);
+        assert_enough_gas_for_mint(items.len() as u64);
+
+        let collection_owner = env::predecessor_account_id();
+        let collection_metadata = self
+            .collections
+            .get(&collection_id)
+            .expect("Error: no such collection id.");
+        assert_eq!(collection_metadata.owner_id,
+                   collection_owner,
+                   "Only owner of collection can mint to collection");
+        for item in items.iter() {
+            assert!(item.token_metadata.copies.unwrap_or(1) == 1,
+                    "Every collection can have only one copy of NFT.");
+            if let Some(royalties) = &item.payout {
+                royalties.assert_valid();
+            }
+        }
+
+        let initial_storage_usage = env::storage_usage();
+        let extra = json!({
+            "collection_id": collection_metadata.collection_id,
+            "title": collection_metadata.title
+        }).to_string();
+        self.assert_collection_tokens_not_rebuilding();
+        let mut collection_tokens = self.internal_collection_tokens(&collection_id);
+
+        let mut minted_ids = vec![];
+        // owners in order of the first token they receive
+        let mut minted_by_owner: Vec<(AccountId, Vec<TokenId>)> = vec![];
+        for item in items {
+            let token_id = format!("{}{}{}", TOKEN_TAG, DELIMITER, self.next_token());
+            let mut token_metadata = item.token_metadata;
+            token_metadata.extra = Some(extra.clone());
+
+            self.internal_mint_token(token_id.clone(),
+                                     item.token_owner_id.clone(),
+                                     token_metadata,
+                                     item.payout);
+            collection_tokens.push(&token_id);
+            self.internal_add_token_to_owner_in_collection(&item.token_owner_id, &collection_id, &token_id);
+            let token_owner_id = item.token_owner_id;
+            match minted_by_owner.iter_mut().find(|(owner_id, _)| *owner_id == token_owner_id) {
+                Some((_, token_ids)) => token_ids.push(token_id.clone()),
+                None => minted_by_owner.push((token_owner_id, vec![token_id.clone()])),
+            }
+            minted_ids.push(token_id);
+        }
+        self
+            .tokens_by_collection_id
+            .insert(&collection_id, &collection_tokens);
+        self.non_empty_collections.insert(&collection_id);
+        let minted_count = minted_ids.len() as u64;
+        self.internal_update_collection_stats(&collection_id, |stats| stats.minted += minted_count);
+
+        refund_storage_deposit(env::storage_usage() - initial_storage_usage);
+
+        env::log_str(
+            &format!("EVENT_JSON:{}", json!({
+            "standard": "nep171",
+            "version": "1.0.0",
+            "event": "nft_mint",
+            "data": minted_by_owner
+                .iter()
+                .map(|(owner_id, token_ids)| json!({
+                    "owner_id": owner_id,
+                    "token_ids": token_ids
+                }))
+                .collect::<Vec<_>>()
+        }).to_string()));
+
+        minted_ids
+    }
+
+    /// Mints the token without charging storage, callers have to meter it themselves.
+    pub(crate) fn internal_mint_token(&mut self,
+                                      token_id: TokenId,
+                                      token_owner_id: AccountId,
+                                      mut token_metadata: TokenMetadata,
+                                      maybe_royalties: Option<Payout>) {
+        let minted_at = env::block_timestamp();
+        if token_metadata.issued_at.is_none() {
+            // NEP-177 uses milliseconds, `token_minted_at` keeps nanoseconds
+            token_metadata.issued_at = Some((minted_at / 1_000_000).to_string());
+        }
+        self.assert_token_id_unused(&token_id);
+        self
+            .tokens
+            .internal_mint_with_refund(token_id.clone(),
+                                       token_owner_id,
+                                       Some(token_metadata),
+                                       None);
+        if let Some(royalties) = maybe_royalties {
+            self.payouts.insert(&token_id, &royalties);
+        }
+        self.token_creators.insert(&token_id, &env::predecessor_account_id());
+        self.token_minted_at.insert(&token_id, &minted_at);
+    }
+
+    /// Ids of burned tokens stay taken, so a new token can't inherit their history.
+    pub(crate) fn assert_token_id_unused(&self, token_id: &TokenId) {
+        assert!(self.tokens.owner_by_id.get(token_id).is_none() && !self.burned_tokens.contains(token_id),
+                "Token id is already taken");
+    }
+}
+
+pub(crate) fn assert_enough_gas_for_mint(tokens_count: u64) {
+    let gas_left = env::prepaid_gas().0 - env::used_gas().0;
+    assert!(gas_left >= GAS_FOR_MINT * tokens_count,
+            "Not enough gas to mint {} tokens", tokens_count);
+}
+
+pub(crate) fn assert_at_least_one_yocto() {
+    assert!(env::attached_deposit() >= 1, "Requires attached deposit of at least 1 yoctoNEAR");
+}
+
+/// Charges `storage_used` bytes from the attached deposit and refunds the rest to the caller.
+pub(crate) fn refund_storage_deposit(storage_used: u64) {
+    let required_cost = env::storage_byte_cost() * storage_used as u128;
+    let attached_deposit = env::attached_deposit();
+    assert!(required_cost <= attached_deposit,
+            "Must attach {} yoctoNEAR to cover storage", required_cost);
+
+    let refund = attached_deposit - required_cost;
+    if refund > 1 {
+        Promise::new(env::predecessor_account_id()).transfer(refund);
+    }
+}
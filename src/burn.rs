@@ -0,0 +1,76 @@
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Burns the token. Owner can burn any own token, issuer of a soulbound
+    /// collection can also revoke tokens of it.
+    #[payable]
+    pub fn nft_burn(&mut self, token_id: TokenId) {
+        assert_one_yocto();
+        let owner_id = self
+            .tokens.owner_by_id
+            .get(&token_id)
+            .expect("Error: no such token id.");
+        let collection_id = self.internal_token_collection_id(&token_id);
+        if collection_id.is_some() {
+            // the rebuild walks token metadata, a burned token would be dropped from the vector
+            self.assert_collection_tokens_not_rebuilding();
+        }
+        let caller_id = env::predecessor_account_id();
+        let is_issuer = collection_id
+            .as_ref()
+            .filter(|collection_id|
+                self.internal_collection_transfer_policy(collection_id) == TransferPolicy::Soulbound)
+            .and_then(|collection_id| self.collections.get(collection_id))
+            .map(|collection| collection.owner_id == caller_id)
+            .unwrap_or(false);
+        assert!(caller_id == owner_id || is_issuer, "Only owner or issuer can burn the token");
+        self.assert_token_has_no_bids(&token_id);
+
+        self.tokens.owner_by_id.remove(&token_id);
+        self.burned_tokens.insert(&token_id);
+        if let Some(token_metadata_by_id) = &mut self.tokens.token_metadata_by_id {
+            token_metadata_by_id.remove(&token_id);
+        }
+        if let Some(tokens_per_owner) = &mut self.tokens.tokens_per_owner {
+            if let Some(mut token_ids) = tokens_per_owner.get(&owner_id) {
+                token_ids.remove(&token_id);
+                if token_ids.is_empty() {
+                    tokens_per_owner.remove(&owner_id);
+                } else {
+                    tokens_per_owner.insert(&owner_id, &token_ids);
+                }
+            }
+        }
+        self.internal_remove_approval_constraints(&token_id);
+        if let Some(approvals_by_id) = &mut self.tokens.approvals_by_id {
+            approvals_by_id.remove(&token_id);
+        }
+        if let Some(next_approval_id_by_id) = &mut self.tokens.next_approval_id_by_id {
+            next_approval_id_by_id.remove(&token_id);
+        }
+        self.payouts.remove(&token_id);
+        self.internal_remove_listing(&token_id);
+        self.internal_cancel_auction(&token_id);
+
+        // burned ids stay in the collection token vector, views skip them and supply subtracts them
+        if let Some(collection_id) = collection_id {
+            self.internal_remove_token_from_owner_in_collection(&owner_id, &collection_id, &token_id);
+            self.internal_update_collection_stats(&collection_id, |stats| stats.burned += 1);
+        }
+
+        env::log_str(
+            &format!("EVENT_JSON:{}", json!({
+            "standard": "nep171",
+            "version": "1.0.0",
+            "event": "nft_burn",
+            "data": [
+                {
+                    "owner_id": owner_id,
+                    "authorized_id": if caller_id != owner_id { Some(caller_id) } else { None },
+                    "token_ids": [token_id]
+                }
+            ]
+        }).to_string()));
+    }
+}
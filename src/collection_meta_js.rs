@@ -1,4 +1,5 @@
 use crate::*;
+use near_sdk::json_types::U64;
 use near_sdk::borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Serialize, Deserialize};
 
@@ -10,4 +11,9 @@ pub struct CollectionMetadataJs {
     pub media: String,
     pub reference: Option<String>,
     pub custom_collection_id: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub category: Option<String>,
+    pub transfer_policy: Option<TransferPolicy>,
+    /// Tokens of the collection can't be transferred before this time, in nanoseconds.
+    pub locked_until: Option<U64>,
 }
\ No newline at end of file
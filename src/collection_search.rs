@@ -0,0 +1,158 @@
+use crate::*;
+
+const MAX_TAGS: usize = 5;
+const MAX_TAG_LEN: usize = 32;
+/// Titles are indexed by lowercase prefixes up to this length, longer queries
+/// are filtered from the longest indexed prefix.
+const MAX_TITLE_PREFIX_LEN: usize = 10;
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CollectionTags {
+    pub tags: Vec<String>,
+    pub category: Option<String>,
+}
+
+/// Lowercases the tag and checks it consists of latin letters, digits and hyphens.
+fn normalize_tag(tag: &str) -> String {
+    let tag = tag.trim().to_lowercase();
+    assert!(!tag.is_empty() && tag.len() <= MAX_TAG_LEN,
+            "Tag must be from 1 to {} characters long", MAX_TAG_LEN);
+    assert!(tag.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-'),
+            "Tag can contain only latin letters, digits and hyphens: {}", tag);
+    tag
+}
+
+fn title_prefixes(title: &str) -> Vec<String> {
+    let title = title.trim().to_lowercase();
+    title
+        .char_indices()
+        .map(|(i, c)| title[..i + c.len_utf8()].to_string())
+        .take(MAX_TITLE_PREFIX_LEN)
+        .collect()
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_collection_tags(&self, collection_id: CollectionId) -> Option<CollectionTags> {
+        self.collection_tags.get(&collection_id)
+    }
+
+    /// Hidden collections are skipped by both searches.
+    pub fn search_collections_by_tag(&self, tag: String, from: u64, limit: u64) -> CollectionsBatch {
+        let collection_ids = self.collections_by_tag.get(&normalize_tag(&tag));
+        self.internal_collections_batch(collection_ids, from, limit, |metadata|
+            !self.internal_is_collection_hidden(&metadata.collection_id))
+    }
+
+    pub fn search_collections_by_title_prefix(&self, prefix: String, from: u64, limit: u64) -> CollectionsBatch {
+        let prefix = prefix.trim().to_lowercase();
+        assert!(!prefix.is_empty(), "Prefix can't be empty");
+        let indexed_prefix = title_prefixes(&prefix).pop().unwrap();
+        let collection_ids = self.collections_by_title_prefix.get(&indexed_prefix);
+        self.internal_collections_batch(collection_ids, from, limit, |metadata|
+            metadata.title.trim().to_lowercase().starts_with(&prefix) &&
+                !self.internal_is_collection_hidden(&metadata.collection_id))
+    }
+
+    /// Indexes titles of collections created before search existed.
+    #[private]
+    pub fn index_collections_search(&mut self, from: u64, limit: u64) -> u64 {
+        let size = self.collections.len();
+        let to = std::cmp::min(from.saturating_add(limit), size);
+        for i in from..to {
+            let metadata = self.collections.values_as_vector().get(i).unwrap();
+            if self.collection_tags.get(&metadata.collection_id).is_none() {
+                self.internal_index_collection_search(&metadata, vec![], None);
+            }
+        }
+        to
+    }
+
+    /// Collections at `[from, from + limit)` of the index which pass `filter`,
+    /// `total_count` is the size of the whole index.
+    pub(crate) fn internal_collections_batch<F>(&self,
+                                     collection_ids: Option<UnorderedSet<CollectionId>>,
+                                     from: u64,
+                                     limit: u64,
+                                     filter: F) -> CollectionsBatch
+        where F: Fn(&CollectionMetadata) -> bool {
+        let collection_ids = match collection_ids {
+            Some(collection_ids) => collection_ids,
+            None => return CollectionsBatch {
+                collections: vec![],
+                has_next_batch: false,
+                total_count: 0,
+            }
+        };
+        let size = collection_ids.len();
+        let to = std::cmp::min(from.saturating_add(limit), size);
+        let collections = (from..to)
+            .filter_map(|i| collection_ids.as_vector().get(i))
+            .filter_map(|collection_id| self.collections.get(&collection_id))
+            .filter(|metadata| filter(metadata))
+            .collect();
+        CollectionsBatch {
+            collections,
+            has_next_batch: to < size,
+            total_count: size,
+        }
+    }
+
+    pub(crate) fn internal_index_collection_search(&mut self,
+                                                   metadata: &CollectionMetadata,
+                                                   tags: Vec<String>,
+                                                   category: Option<String>) {
+        let mut normalized_tags: Vec<String> = tags.iter().map(|tag| normalize_tag(tag)).collect();
+        normalized_tags.sort();
+        normalized_tags.dedup();
+        assert!(normalized_tags.len() <= MAX_TAGS, "Collection can have at most {} tags", MAX_TAGS);
+
+        for tag in normalized_tags.iter() {
+            let mut collection_ids = self
+                .collections_by_tag
+                .get(tag)
+                .unwrap_or_else(||
+                    UnorderedSet::new(StorageKey::CollectionsByTagInner {
+                        tag_hash: hash_string(tag)
+                    }.try_to_vec().unwrap()));
+            collection_ids.insert(&metadata.collection_id);
+            self.collections_by_tag.insert(tag, &collection_ids);
+        }
+        for prefix in title_prefixes(&metadata.title) {
+            let mut collection_ids = self
+                .collections_by_title_prefix
+                .get(&prefix)
+                .unwrap_or_else(||
+                    UnorderedSet::new(StorageKey::CollectionsByTitlePrefixInner {
+                        prefix_hash: hash_string(&prefix)
+                    }.try_to_vec().unwrap()));
+            collection_ids.insert(&metadata.collection_id);
+            self.collections_by_title_prefix.insert(&prefix, &collection_ids);
+        }
+
+        if !normalized_tags.is_empty() || category.is_some() {
+            self.collection_tags.insert(&metadata.collection_id, &CollectionTags {
+                tags: normalized_tags,
+                category: category.map(|category| normalize_tag(&category)),
+            });
+        }
+    }
+
+    pub(crate) fn internal_unindex_collection_search(&mut self, metadata: &CollectionMetadata) {
+        if let Some(collection_tags) = self.collection_tags.remove(&metadata.collection_id) {
+            for tag in collection_tags.tags.iter() {
+                if let Some(mut collection_ids) = self.collections_by_tag.get(tag) {
+                    collection_ids.remove(&metadata.collection_id);
+                    self.collections_by_tag.insert(tag, &collection_ids);
+                }
+            }
+        }
+        for prefix in title_prefixes(&metadata.title) {
+            if let Some(mut collection_ids) = self.collections_by_title_prefix.get(&prefix) {
+                collection_ids.remove(&metadata.collection_id);
+                self.collections_by_title_prefix.insert(&prefix, &collection_ids);
+            }
+        }
+    }
+}
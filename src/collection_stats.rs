@@ -0,0 +1,45 @@
+use crate::*;
+use near_sdk::json_types::U64;
+
+#[derive(BorshSerialize, BorshDeserialize, Default)]
+pub struct CollectionStats {
+    pub minted: u64,
+    pub burned: u64,
+    pub holders: u64,
+    pub transfers: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CollectionStatsJs {
+    pub collection_id: CollectionId,
+    pub supply: U64,
+    pub minted: U64,
+    pub burned: U64,
+    pub holders: U64,
+    pub transfers: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_collection_stats(&self, collection_id: CollectionId) -> Option<CollectionStatsJs> {
+        self.collections.get(&collection_id)?;
+        let stats = self.collection_stats.get(&collection_id).unwrap_or_default();
+        Some(CollectionStatsJs {
+            // counters of collections created before stats existed lag behind until backfilled
+            supply: U64(self.internal_collection_supply(&collection_id)),
+            collection_id,
+            minted: U64(stats.minted),
+            burned: U64(stats.burned),
+            holders: U64(stats.holders),
+            transfers: U64(stats.transfers),
+        })
+    }
+
+    pub(crate) fn internal_update_collection_stats<F>(&mut self, collection_id: &CollectionId, update: F)
+        where F: FnOnce(&mut CollectionStats) {
+        let mut stats = self.collection_stats.get(collection_id).unwrap_or_default();
+        update(&mut stats);
+        self.collection_stats.insert(collection_id, &stats);
+    }
+}
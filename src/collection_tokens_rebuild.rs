@@ -0,0 +1,241 @@
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Rebuilds token vectors of collections from `extra` of token metadata after
+    /// `migrate`. Generated ids `token-1`, `token-2`, ... are walked in mint order, so
+    /// vectors keep the order views rely on; collection tokens of the old layout all
+    /// have such ids as copies can't be minted to collections. Every call processes up
+    /// to `limit` ids after `from`, then clears leftovers of the old layout in chunks of
+    /// `limit` keys. Returns the cursor for the next call, `None` once everything is
+    /// done. Minting to collections and methods walking collection vectors are paused
+    /// until then, so backfills like `index_tokens_by_owner_in_collection` must run
+    /// after it.
+    #[private]
+    pub fn rebuild_collection_tokens(&mut self, from: u64, limit: u64) -> Option<u64> {
+        let mut rebuilt = self
+            .rebuilt_collection_tokens
+            .take()
+            .expect("Collection tokens aren't being rebuilt");
+        let to = std::cmp::min(from.saturating_add(limit), self.total_minted as u64);
+
+        for index in (from + 1)..(to + 1) {
+            let token_id = format!("{}{}{}", TOKEN_TAG, DELIMITER, index);
+            let collection_id = match self.internal_token_collection_id(&token_id) {
+                Some(collection_id) => collection_id,
+                None => continue,
+            };
+            let mut collection_tokens = if rebuilt.insert(&collection_id) {
+                self.internal_track_legacy_collection_tokens(&collection_id);
+                new_collection_tokens(&collection_id)
+            } else {
+                self.internal_collection_tokens(&collection_id)
+            };
+            collection_tokens.push(&token_id);
+            self.tokens_by_collection_id.insert(&collection_id, &collection_tokens);
+        }
+        if to < self.total_minted as u64 {
+            self.rebuilt_collection_tokens = Some(rebuilt);
+            return Some(to);
+        }
+
+        let rebuilt_ids: Vec<CollectionId> = rebuilt.iter().take(limit as usize).collect();
+        for collection_id in rebuilt_ids.iter() {
+            rebuilt.remove(collection_id);
+        }
+        let removed_keys = limit.saturating_sub(rebuilt_ids.len() as u64);
+        if !rebuilt.is_empty() || !self.internal_clear_legacy_collection_tokens(removed_keys) {
+            self.rebuilt_collection_tokens = Some(rebuilt);
+            return Some(to);
+        }
+
+        env::log_str(&json!({
+            "type": "rebuild_collection_tokens",
+            "data": {
+                "collections": self.collections.len()
+            }
+        }).to_string());
+        None
+    }
+
+    pub(crate) fn assert_collection_tokens_not_rebuilding(&self) {
+        assert!(self.rebuilt_collection_tokens.is_none(),
+                "Collection tokens are being rebuilt, try again later");
+    }
+
+    /// Remembers the old vector of the collection, which was shared by all collections
+    /// of its owner, before it's replaced.
+    fn internal_track_legacy_collection_tokens(&mut self, collection_id: &CollectionId) {
+        let owner_id = match self.collections.get(collection_id) {
+            Some(collection) => collection.owner_id,
+            None => return,
+        };
+        if let Some(old_tokens) = self.tokens_by_collection_id.get(collection_id) {
+            self.legacy_collection_tokens.push(&(owner_id, old_tokens.len()));
+        }
+    }
+
+    /// Removes up to `limit` keys of old vectors, returns `true` once all are removed.
+    fn internal_clear_legacy_collection_tokens(&mut self, limit: u64) -> bool {
+        let mut removed = 0;
+        while removed < limit {
+            let (owner_id, len) = match self.legacy_collection_tokens.pop() {
+                Some(legacy) => legacy,
+                None => return true,
+            };
+            let prefix = StorageKey::TokensByCollectionIdInner {
+                collection_id_hash: hash_account_id(&owner_id)
+            }.try_to_vec().unwrap();
+            // a collection with the same id as the owner account reuses the prefix
+            let kept = self
+                .tokens_by_collection_id
+                .get(&owner_id.to_string())
+                .map(|tokens| tokens.len())
+                .unwrap_or(0);
+            let count = std::cmp::min(len.saturating_sub(kept), limit - removed);
+            for index in (len - count)..len {
+                let mut key = prefix.clone();
+                key.extend_from_slice(&index.to_le_bytes());
+                env::storage_remove(&key);
+            }
+            removed += count;
+            if len - count > kept {
+                self.legacy_collection_tokens.push(&(owner_id, len - count));
+            }
+        }
+        self.legacy_collection_tokens.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, ONE_NEAR};
+
+    use crate::tests::{context, contract_id, token_metadata};
+    use super::*;
+
+    /// Layout of the contract before `migrate`.
+    #[derive(BorshSerialize)]
+    struct BaselineContract {
+        metadata: NFTContractMetadata,
+        tokens: NonFungibleToken,
+        payouts: LookupMap<TokenId, Payout>,
+        collections: UnorderedMap<CollectionId, CollectionMetadata>,
+        collections_by_owner_id: LookupMap<AccountId, UnorderedSet<CollectionId>>,
+        tokens_by_collection_id: LookupMap<CollectionId, Vector<TokenId>>,
+        total_minted: u128,
+        total_collections: u128,
+    }
+
+    fn owner_prefix() -> Vec<u8> {
+        StorageKey::TokensByCollectionIdInner {
+            collection_id_hash: hash_account_id(&accounts(0))
+        }.try_to_vec().unwrap()
+    }
+
+    /// Writes collections `aaa` and `bbb` of `accounts(0)` with interleaved tokens in
+    /// the old layout, where token vectors of one owner share a prefix, and migrates.
+    fn migrate_baseline_state() -> Contract {
+        testing_env!(context(contract_id(), 0).build());
+        let mut state = BaselineContract {
+            metadata: NFTContractMetadata {
+                spec: "nft-1.0.0".to_string(),
+                name: "Mjolnear".to_string(),
+                symbol: "MJOL".to_string(),
+                icon: None,
+                base_uri: None,
+                reference: None,
+                reference_hash: None,
+            },
+            tokens: NonFungibleToken::new(
+                StorageKey::NonFungibleToken,
+                contract_id(),
+                Some(StorageKey::TokenMetadata),
+                Some(StorageKey::Enumeration),
+                Some(StorageKey::Approval),
+            ),
+            payouts: LookupMap::new(StorageKey::Royalties),
+            collections: UnorderedMap::new(StorageKey::CollectionsNew),
+            collections_by_owner_id: LookupMap::new(StorageKey::CollectionsByOwnerId),
+            tokens_by_collection_id: LookupMap::new(StorageKey::TokensByCollectionId),
+            total_minted: 0,
+            total_collections: 0,
+        };
+        let mut owner_collections = UnorderedSet::new(StorageKey::CollectionsByOwnerIdInner {
+            account_id_hash: hash_account_id(&accounts(0))
+        }.try_to_vec().unwrap());
+        let mut collection_tokens = vec![];
+        for collection_id in ["aaa", "bbb"] {
+            state.collections.insert(&collection_id.to_string(), &CollectionMetadata {
+                collection_id: collection_id.to_string(),
+                collection_contract: MJOL_CONTRACT.to_string(),
+                owner_id: accounts(0),
+                title: collection_id.to_string(),
+                desc: "".to_string(),
+                media: "".to_string(),
+                reference: None,
+            });
+            owner_collections.insert(&collection_id.to_string());
+            collection_tokens.push(Vector::new(owner_prefix()));
+            state.total_collections += 1;
+        }
+        state.collections_by_owner_id.insert(&accounts(0), &owner_collections);
+
+        for index in 1..=7 {
+            let collection_id = if index % 3 == 1 { "aaa" } else { "bbb" };
+            let token_id = format!("{}{}{}", TOKEN_TAG, DELIMITER, index);
+            let mut metadata = token_metadata(None);
+            metadata.extra = Some(json!({
+                "collection_id": collection_id,
+                "title": collection_id
+            }).to_string());
+            state.tokens.internal_mint_with_refund(token_id.clone(), accounts(1), Some(metadata), None);
+            collection_tokens[(collection_id == "bbb") as usize].push(&token_id);
+            state.total_minted += 1;
+        }
+        state.tokens_by_collection_id.insert(&"aaa".to_string(), &collection_tokens[0]);
+        state.tokens_by_collection_id.insert(&"bbb".to_string(), &collection_tokens[1]);
+        env::state_write(&state);
+
+        Contract::migrate()
+    }
+
+    fn collection_token_ids(contract: &Contract, collection_id: &str) -> Vec<TokenId> {
+        contract.tokens_by_collection_id.get(&collection_id.to_string()).unwrap().to_vec()
+    }
+
+    #[test]
+    fn rebuild_splits_shared_vectors_in_mint_order() {
+        let mut contract = migrate_baseline_state();
+        let mut cursor = 0;
+        let mut calls = 0;
+        while let Some(next) = contract.rebuild_collection_tokens(cursor, 2) {
+            cursor = next;
+            calls += 1;
+            assert!(calls < 20, "Rebuild doesn't finish");
+        }
+
+        assert_eq!(collection_token_ids(&contract, "aaa"), vec!["token-1", "token-4", "token-7"]);
+        assert_eq!(collection_token_ids(&contract, "bbb"), vec!["token-2", "token-3", "token-5", "token-6"]);
+        for index in 0..7u64 {
+            let mut key = owner_prefix();
+            key.extend_from_slice(&index.to_le_bytes());
+            assert!(!env::storage_has_key(&key), "Old key {} is left", index);
+        }
+
+        testing_env!(context(accounts(0), ONE_NEAR).build());
+        contract.nft_mint(accounts(1), token_metadata(None), None, Some("aaa".to_string()), None, None);
+        assert_eq!(collection_token_ids(&contract, "aaa"), vec!["token-1", "token-4", "token-7", "token-8"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Collection tokens are being rebuilt")]
+    fn mint_to_collection_waits_for_rebuild() {
+        let mut contract = migrate_baseline_state();
+        assert!(contract.rebuild_collection_tokens(0, 2).is_some());
+
+        testing_env!(context(accounts(0), ONE_NEAR).build());
+        contract.nft_mint(accounts(1), token_metadata(None), None, Some("aaa".to_string()), None, None);
+    }
+}
@@ -0,0 +1,11 @@
+use near_sdk::AccountId;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeConfig {
+    pub treasury_id: AccountId,
+    /// Platform fee in basis points of the sale balance.
+    pub fee: u32,
+}
@@ -0,0 +1,482 @@
+use crate::*;
+use near_sdk::{Gas, PromiseResult};
+
+const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_RESOLVE_FT_PAYOUT: Gas = Gas(5_000_000_000_000);
+
+/// Primary sale of a collection: anyone can mint a token with `token_metadata`
+/// and royalties `payout` by transferring `price` of `ft_token_id` to the contract.
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtMintSale {
+    pub ft_token_id: AccountId,
+    pub price: U128,
+    pub token_metadata: TokenMetadata,
+    pub payout: Option<Payout>,
+}
+
+/// `msg` of `ft_transfer_call` to this contract.
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde", tag = "action", rename_all = "snake_case")]
+enum FtTransferMsg {
+    BuyToken { token_id: TokenId },
+    Mint { collection_id: CollectionId },
+}
+
+#[near_bindgen]
+impl Contract {
+    #[private]
+    pub fn add_ft_token(&mut self, ft_token_id: AccountId) {
+        self.ft_tokens.insert(&ft_token_id);
+
+        env::log_str(&json!({
+            "type": "add_ft_token",
+            "data": {
+                "ft_token_id": ft_token_id
+            }
+        }).to_string())
+    }
+
+    #[private]
+    pub fn remove_ft_token(&mut self, ft_token_id: AccountId) {
+        self.ft_tokens.remove(&ft_token_id);
+
+        env::log_str(&json!({
+            "type": "remove_ft_token",
+            "data": {
+                "ft_token_id": ft_token_id
+            }
+        }).to_string())
+    }
+
+    pub fn get_ft_tokens(&self) -> Vec<AccountId> {
+        self.ft_tokens.to_vec()
+    }
+
+    /// Opens the primary sale of the collection. Storage of the sale is paid from
+    /// the attached deposit, storage of tokens minted later from the deposit made
+    /// with `deposit_collection_mint_storage`.
+    #[payable]
+    pub fn set_collection_ft_mint_sale(&mut self, collection_id: CollectionId, sale: FtMintSale) {
+        self.assert_collection_owner(&collection_id, "Only owner of collection can set mint sale");
+        self.assert_ft_token_whitelisted(&sale.ft_token_id);
+        assert!(sale.price.0 > 0, "Price must be positive");
+        assert!(sale.token_metadata.copies.unwrap_or(1) == 1,
+                "Every collection can have only one copy of NFT.");
+        if let Some(royalties) = &sale.payout {
+            royalties.assert_valid();
+        }
+
+        let initial_storage_usage = env::storage_usage();
+        self.collection_ft_mint_sales.insert(&collection_id, &sale);
+        refund_storage_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+
+        env::log_str(&json!({
+            "type": "set_collection_ft_mint_sale",
+            "data": {
+                "collection_id": collection_id,
+                "ft_token_id": sale.ft_token_id,
+                "price": sale.price
+            }
+        }).to_string());
+    }
+
+    /// Removes the sale, released storage and the rest of the mint storage deposit
+    /// are refunded to the owner.
+    #[payable]
+    pub fn remove_collection_ft_mint_sale(&mut self, collection_id: CollectionId) {
+        assert_one_yocto();
+        let owner_id = self.assert_collection_owner(&collection_id, "Only owner of collection can remove mint sale");
+        self.internal_remove_collection_ft_mint_sale(&collection_id, owner_id);
+    }
+
+    pub fn get_collection_ft_mint_sale(&self, collection_id: CollectionId) -> Option<FtMintSale> {
+        self.collection_ft_mint_sales.get(&collection_id)
+    }
+
+    /// Adds the attached deposit to the NEAR balance which pays for storage of tokens
+    /// minted by the sale of the collection. Returns the new balance.
+    #[payable]
+    pub fn deposit_collection_mint_storage(&mut self, collection_id: CollectionId) -> U128 {
+        self.assert_collection_owner(&collection_id, "Only owner of collection can deposit mint storage");
+        let initial_storage_usage = env::storage_usage();
+        let deposit = self.collection_mint_storage_deposits.get(&collection_id).unwrap_or(0);
+        self.collection_mint_storage_deposits.insert(&collection_id, &deposit);
+        // the first deposit pays for its own entry
+        let storage_cost = env::storage_byte_cost() * (env::storage_usage() - initial_storage_usage) as u128;
+        assert!(env::attached_deposit() > storage_cost,
+                "Must attach more than {} yoctoNEAR", storage_cost);
+        let deposit = deposit + env::attached_deposit() - storage_cost;
+        self.collection_mint_storage_deposits.insert(&collection_id, &deposit);
+
+        env::log_str(&json!({
+            "type": "deposit_collection_mint_storage",
+            "data": {
+                "collection_id": collection_id,
+                "deposit": U128(deposit)
+            }
+        }).to_string());
+
+        U128(deposit)
+    }
+
+    pub fn get_collection_mint_storage_deposit(&self, collection_id: CollectionId) -> U128 {
+        U128(self.collection_mint_storage_deposits.get(&collection_id).unwrap_or(0))
+    }
+
+    /// NEP-141 receiver. `msg` is either `{"action": "buy_token", "token_id": ..}` or
+    /// `{"action": "mint", "collection_id": ..}`, the part of `amount` above the price is returned.
+    pub fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> PromiseOrValue<U128> {
+        let ft_token_id = env::predecessor_account_id();
+        self.assert_ft_token_whitelisted(&ft_token_id);
+        let msg: FtTransferMsg = near_sdk::serde_json::from_str(&msg).expect("Error: invalid msg.");
+
+        let price = match msg {
+            FtTransferMsg::BuyToken { token_id } => {
+                self.assert_enough_gas_for_ft_payout(MAX_LEN_PAYOUT as u64 + 2);
+                self.internal_buy_token(&token_id, &sender_id, amount.0, Some(&ft_token_id)).1
+            }
+            FtTransferMsg::Mint { collection_id } => {
+                self.assert_enough_gas_for_ft_payout(2);
+                self.internal_ft_mint(&collection_id, &sender_id, amount.0, &ft_token_id)
+            }
+        };
+
+        PromiseOrValue::Value(U128(amount.0 - price))
+    }
+
+    /// Handles the result of a payout `ft_transfer`. Failed payouts are kept on the
+    /// contract and can be claimed later with `withdraw_ft_payout`.
+    #[private]
+    pub fn ft_resolve_payout(&mut self, ft_token_id: AccountId, receiver_id: AccountId, amount: U128) {
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return;
+        }
+        let key = (ft_token_id.clone(), receiver_id.clone());
+        let pending = self.ft_pending_payouts.get(&key).unwrap_or(0);
+        self.ft_pending_payouts.insert(&key, &(pending + amount.0));
+
+        env::log_str(&json!({
+            "type": "ft_payout_failed",
+            "data": {
+                "ft_token_id": ft_token_id,
+                "receiver_id": receiver_id,
+                "amount": amount
+            }
+        }).to_string());
+    }
+
+    /// Retries payouts in `ft_token_id` which failed for the caller.
+    #[payable]
+    pub fn withdraw_ft_payout(&mut self, ft_token_id: AccountId) -> U128 {
+        assert_one_yocto();
+        let receiver_id = env::predecessor_account_id();
+        let amount = self
+            .ft_pending_payouts
+            .remove(&(ft_token_id.clone(), receiver_id.clone()))
+            .expect("Error: nothing to withdraw.");
+        self.internal_ft_transfer(&ft_token_id, &receiver_id, amount);
+        U128(amount)
+    }
+
+    pub fn get_ft_pending_payout(&self, ft_token_id: AccountId, account_id: AccountId) -> U128 {
+        U128(self.ft_pending_payouts.get(&(ft_token_id, account_id)).unwrap_or(0))
+    }
+
+    /// Mints the next token of the collection sale to `owner_id`, returns the charged price.
+    fn internal_ft_mint(&mut self,
+                        collection_id: &CollectionId,
+                        owner_id: &AccountId,
+                        amount: u128,
+                        ft_token_id: &AccountId) -> u128 {
+        let sale = self
+            .collection_ft_mint_sales
+            .get(collection_id)
+            .expect("Error: collection has no mint sale.");
+        assert_eq!(&sale.ft_token_id, ft_token_id, "Mint sale is priced in another currency");
+        assert!(amount >= sale.price.0, "Attached amount is less than price {}", sale.price.0);
+        assert_enough_gas_for_mint(1);
+        let collection_metadata = self.collections.get(collection_id).expect("Error: no such collection id.");
+
+        let token_id = format!("{}{}{}", TOKEN_TAG, DELIMITER, self.next_token());
+        let mut token_metadata = sale.token_metadata;
+        token_metadata.extra = Some(json!({
+            "collection_id": collection_metadata.collection_id,
+            "title": collection_metadata.title
+        }).to_string());
+        self.assert_collection_tokens_not_rebuilding();
+        let initial_storage_usage = env::storage_usage();
+        let mut collection_tokens = self.internal_collection_tokens(collection_id);
+        self.internal_mint_token(token_id.clone(), owner_id.clone(), token_metadata, sale.payout);
+        // the predecessor is the token contract, creator is the collection owner
+        self.token_creators.insert(&token_id, &collection_metadata.owner_id);
+        collection_tokens.push(&token_id);
+        self.tokens_by_collection_id.insert(collection_id, &collection_tokens);
+        self.non_empty_collections.insert(collection_id);
+        self.internal_add_token_to_owner_in_collection(owner_id, collection_id, &token_id);
+        self.internal_update_collection_stats(collection_id, |stats| stats.minted += 1);
+        self.internal_charge_mint_storage(collection_id,
+                                          env::storage_usage().saturating_sub(initial_storage_usage));
+
+        let mut payout = HashMap::new();
+        let platform_fee = self.fee_config.fee as u128;
+        let mut fee_amount = 0;
+        if platform_fee > 0 && self.fee_config.treasury_id != collection_metadata.owner_id {
+            fee_amount = payout_part_from_balance(platform_fee, sale.price.0).0;
+            payout.insert(self.fee_config.treasury_id.clone(), U128(fee_amount));
+        }
+        payout.insert(collection_metadata.owner_id, U128(sale.price.0 - fee_amount));
+        self.internal_ft_payout(ft_token_id, &Payout { payout });
+
+        env::log_str(
+            &format!("EVENT_JSON:{}", json!({
+            "standard": "nep171",
+            "version": "1.0.0",
+            "event": "nft_mint",
+            "data": [
+                {
+                    "owner_id": owner_id,
+                    "token_ids": [token_id]
+                }
+            ]
+        }).to_string()));
+
+        sale.price.0
+    }
+
+    /// Drops the sale and the mint storage deposit of the collection, refunding the
+    /// deposit and released storage to `owner_id`.
+    pub(crate) fn internal_remove_collection_ft_mint_sale(&mut self,
+                                                          collection_id: &CollectionId,
+                                                          owner_id: AccountId) {
+        let initial_storage_usage = env::storage_usage();
+        let sale = self.collection_ft_mint_sales.remove(collection_id);
+        let deposit = self.collection_mint_storage_deposits.remove(collection_id).unwrap_or(0);
+        if sale.is_none() && deposit == 0 {
+            return;
+        }
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        let refund = deposit + env::storage_byte_cost() * storage_released as u128;
+        if refund > 0 {
+            Promise::new(owner_id).transfer(refund);
+        }
+
+        env::log_str(&json!({
+            "type": "remove_collection_ft_mint_sale",
+            "data": {
+                "collection_id": collection_id
+            }
+        }).to_string());
+    }
+
+    /// Pays storage of a token minted by the sale from the deposit of the collection.
+    fn internal_charge_mint_storage(&mut self, collection_id: &CollectionId, storage_used: u64) {
+        let storage_cost = env::storage_byte_cost() * storage_used as u128;
+        let deposit = self.collection_mint_storage_deposits.get(collection_id).unwrap_or(0);
+        assert!(deposit >= storage_cost,
+                "Mint storage deposit of collection {} is too low, {} yoctoNEAR is needed",
+                collection_id, storage_cost);
+        self.collection_mint_storage_deposits.insert(collection_id, &(deposit - storage_cost));
+    }
+
+    fn assert_collection_owner(&self, collection_id: &CollectionId, message: &str) -> AccountId {
+        let collection_metadata = self
+            .collections
+            .get(collection_id)
+            .expect("Error: no such collection id.");
+        assert_eq!(collection_metadata.owner_id, env::predecessor_account_id(), "{}", message);
+        collection_metadata.owner_id
+    }
+
+    pub(crate) fn internal_ft_payout(&mut self, ft_token_id: &AccountId, payout: &Payout) {
+        for (receiver_id, amount) in payout.payout.iter() {
+            if amount.0 > 0 {
+                self.internal_ft_transfer(ft_token_id, receiver_id, amount.0);
+            }
+        }
+    }
+
+    fn internal_ft_transfer(&self, ft_token_id: &AccountId, receiver_id: &AccountId, amount: u128) {
+        Promise::new(ft_token_id.clone())
+            .function_call(
+                "ft_transfer".to_string(),
+                json!({
+                    "receiver_id": receiver_id,
+                    "amount": U128(amount)
+                }).to_string().into_bytes(),
+                1,
+                GAS_FOR_FT_TRANSFER)
+            .then(Promise::new(env::current_account_id())
+                .function_call(
+                    "ft_resolve_payout".to_string(),
+                    json!({
+                        "ft_token_id": ft_token_id,
+                        "receiver_id": receiver_id,
+                        "amount": U128(amount)
+                    }).to_string().into_bytes(),
+                    0,
+                    GAS_FOR_RESOLVE_FT_PAYOUT));
+    }
+
+    pub(crate) fn assert_ft_token_whitelisted(&self, ft_token_id: &AccountId) {
+        assert!(self.ft_tokens.contains(ft_token_id), "Token {} is not accepted for payments", ft_token_id);
+    }
+
+    fn assert_enough_gas_for_ft_payout(&self, payouts_count: u64) {
+        let gas_left = env::prepaid_gas().0 - env::used_gas().0;
+        assert!(gas_left >= (GAS_FOR_FT_TRANSFER.0 + GAS_FOR_RESOLVE_FT_PAYOUT.0) * payouts_count,
+                "Not enough gas for {} payouts", payouts_count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig, ONE_NEAR};
+
+    use crate::tests::{collection_metadata_js, context, contract_id, setup_contract, token_metadata};
+    use super::*;
+
+    const PRICE: u128 = 100;
+
+    fn ft_token_id() -> AccountId {
+        "usdc.near".parse().unwrap()
+    }
+
+    fn mint_msg(collection_id: &str) -> String {
+        json!({ "action": "mint", "collection_id": collection_id }).to_string()
+    }
+
+    fn buy_msg(token_id: &str) -> String {
+        json!({ "action": "buy_token", "token_id": token_id }).to_string()
+    }
+
+    /// Contract accepting `ft_token_id()` with a mint sale in a collection of `accounts(0)`.
+    fn setup_mint_sale(storage_deposit: u128) -> (Contract, CollectionId) {
+        let mut contract = setup_contract();
+        testing_env!(context(contract_id(), 0).build());
+        contract.add_ft_token(ft_token_id());
+        testing_env!(context(accounts(0), ONE_NEAR).build());
+        let collection_id = contract.create_collection(collection_metadata_js(Some("mint-sale".to_string()))).collection_id;
+        contract.set_collection_ft_mint_sale(collection_id.clone(), FtMintSale {
+            ft_token_id: ft_token_id(),
+            price: U128(PRICE),
+            token_metadata: token_metadata(None),
+            payout: None,
+        });
+        if storage_deposit > 0 {
+            testing_env!(context(accounts(0), storage_deposit).build());
+            contract.deposit_collection_mint_storage(collection_id.clone());
+        }
+        (contract, collection_id)
+    }
+
+    fn list_token(contract: &mut Contract, token_id: &str, currency: Option<AccountId>) {
+        testing_env!(context(contract_id(), 0).build());
+        contract.add_ft_token(ft_token_id());
+        testing_env!(context(accounts(0), ONE_NEAR).build());
+        contract.nft_mint(accounts(1), token_metadata(None), None, None, Some(token_id.to_string()), None);
+        testing_env!(context(accounts(1), ONE_NEAR).build());
+        contract.list_token(token_id.to_string(), U128(PRICE), currency);
+    }
+
+    fn returned_amount(result: PromiseOrValue<U128>) -> u128 {
+        match result {
+            PromiseOrValue::Value(amount) => amount.0,
+            PromiseOrValue::Promise(_) => panic!("Expected a value"),
+        }
+    }
+
+    #[test]
+    fn mint_refunds_overpayment_and_charges_storage_deposit() {
+        let (mut contract, collection_id) = setup_mint_sale(ONE_NEAR);
+        let deposit = contract.get_collection_mint_storage_deposit(collection_id.clone()).0;
+
+        testing_env!(context(ft_token_id(), 0).build());
+        let result = contract.ft_on_transfer(accounts(2), U128(PRICE + 50), mint_msg(&collection_id));
+        assert_eq!(returned_amount(result), 50);
+
+        let token_id = format!("{}{}{}", TOKEN_TAG, DELIMITER, 1);
+        assert_eq!(contract.nft_token(token_id).unwrap().owner_id, accounts(2));
+        assert_eq!(contract.nft_collection_supply(collection_id.clone()), "1");
+        assert!(contract.get_collection_mint_storage_deposit(collection_id).0 < deposit);
+    }
+
+    #[test]
+    #[should_panic(expected = "Mint storage deposit of collection")]
+    fn mint_requires_storage_deposit() {
+        let (mut contract, collection_id) = setup_mint_sale(0);
+        testing_env!(context(ft_token_id(), 0).build());
+        contract.ft_on_transfer(accounts(2), U128(PRICE), mint_msg(&collection_id));
+    }
+
+    #[test]
+    #[should_panic(expected = "Mint sale is priced in another currency")]
+    fn mint_rejects_other_currency() {
+        let (mut contract, collection_id) = setup_mint_sale(ONE_NEAR);
+        let other_ft_token_id: AccountId = "usdt.near".parse().unwrap();
+        testing_env!(context(contract_id(), 0).build());
+        contract.add_ft_token(other_ft_token_id.clone());
+        testing_env!(context(other_ft_token_id, 0).build());
+        contract.ft_on_transfer(accounts(2), U128(PRICE), mint_msg(&collection_id));
+    }
+
+    #[test]
+    fn buy_token_refunds_overpayment() {
+        let mut contract = setup_contract();
+        list_token(&mut contract, "listed", Some(ft_token_id()));
+
+        testing_env!(context(ft_token_id(), 0).build());
+        let result = contract.ft_on_transfer(accounts(2), U128(PRICE + 1), buy_msg("listed"));
+        assert_eq!(returned_amount(result), 1);
+        assert_eq!(contract.nft_token("listed".to_string()).unwrap().owner_id, accounts(2));
+        assert!(contract.get_listing("listed".to_string()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is listed in another currency")]
+    fn buy_token_rejects_other_currency() {
+        let mut contract = setup_contract();
+        list_token(&mut contract, "listed", None);
+
+        testing_env!(context(ft_token_id(), 0).build());
+        contract.ft_on_transfer(accounts(2), U128(PRICE), buy_msg("listed"));
+    }
+
+    #[test]
+    fn failed_payout_is_kept_for_withdrawal() {
+        let mut contract = setup_contract();
+        testing_env!(context(contract_id(), 0).build(),
+                     VMConfig::test(),
+                     RuntimeFeesConfig::test(),
+                     HashMap::default(),
+                     vec![PromiseResult::Failed]);
+        contract.ft_resolve_payout(ft_token_id(), accounts(1), U128(PRICE));
+        contract.ft_resolve_payout(ft_token_id(), accounts(1), U128(PRICE));
+        assert_eq!(contract.get_ft_pending_payout(ft_token_id(), accounts(1)).0, 2 * PRICE);
+
+        testing_env!(context(contract_id(), 0).build(),
+                     VMConfig::test(),
+                     RuntimeFeesConfig::test(),
+                     HashMap::default(),
+                     vec![PromiseResult::Successful(vec![])]);
+        contract.ft_resolve_payout(ft_token_id(), accounts(2), U128(PRICE));
+        assert_eq!(contract.get_ft_pending_payout(ft_token_id(), accounts(2)).0, 0);
+    }
+
+    #[test]
+    fn removed_collection_drops_mint_sale_and_deposit() {
+        let (mut contract, collection_id) = setup_mint_sale(ONE_NEAR);
+        testing_env!(context(contract_id(), 0).build());
+        contract.remove_collection(collection_id.clone(), accounts(0));
+        assert!(contract.get_collection_ft_mint_sale(collection_id.clone()).is_none());
+        assert_eq!(contract.get_collection_mint_storage_deposit(collection_id.clone()).0, 0);
+
+        // a new collection under the same id can't take the old deposit
+        testing_env!(context(accounts(3), ONE_NEAR).build());
+        contract.create_collection(collection_metadata_js(Some(collection_id.clone())));
+        assert_eq!(contract.get_collection_mint_storage_deposit(collection_id).0, 0);
+    }
+}
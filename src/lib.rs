@@ -7,20 +7,50 @@ use near_contract_standards::non_fungible_token::core::{NonFungibleTokenCore, No
 use near_contract_standards::non_fungible_token::metadata::{NFTContractMetadata, TokenMetadata};
 use near_sdk::{AccountId, assert_one_yocto, BorshStorageKey, CryptoHash, env, near_bindgen, PanicOnDefault, Promise, PromiseOrValue};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet, Vector};
+use near_sdk::collections::{LookupMap, LookupSet, UnorderedMap, UnorderedSet, Vector};
 use near_sdk::env::is_valid_account_id;
-use near_sdk::json_types::U128;
+use near_sdk::json_types::{U128, U64};
 use near_sdk::serde_json::json;
-use regex::Regex;
 use serde::{Deserialize, Serialize};
 
+use crate::batch_mint::{assert_at_least_one_yocto, assert_enough_gas_for_mint, refund_storage_deposit};
+use crate::approval::ApprovalConstraints;
 use crate::collection_meta_js::CollectionMetadataJs;
+use crate::collection_search::CollectionTags;
+use crate::collection_stats::CollectionStats;
+use crate::fee_config::FeeConfig;
+use crate::ft_payments::FtMintSale;
+use crate::auction::Auction;
+use crate::market::Listing;
+use crate::offers::{Offer, OfferId};
+use crate::moderation::{CollectionModeration, CollectionStatus};
+use crate::operators::OperatorApproval;
 use crate::payouts::Payouts;
+use crate::slug::{assert_valid_collection_slug, assert_valid_token_id};
+use crate::transfer_policy::TransferPolicy;
 use crate::whitelisted_meta::WhitelistedToken;
 
 mod payouts;
 mod collection_meta_js;
 mod whitelisted_meta;
+mod fee_config;
+mod royalties;
+mod provenance;
+mod batch_mint;
+mod owner_collection_tokens;
+mod collection_stats;
+mod collection_search;
+mod moderation;
+mod slug;
+mod collection_tokens_rebuild;
+mod transfer_policy;
+mod approval;
+mod burn;
+mod operators;
+mod market;
+mod auction;
+mod offers;
+mod ft_payments;
 
 #[derive(BorshSerialize, BorshStorageKey)]
 enum StorageKey {
@@ -33,7 +63,47 @@ enum StorageKey {
     CollectionsByOwnerId,
     CollectionsByOwnerIdInner { account_id_hash: CryptoHash },
     TokensByCollectionId,
-    TokensByCollectionIdInner { account_id_hash: CryptoHash },
+    TokensByCollectionIdInner { collection_id_hash: CryptoHash },
+    TokenCreators,
+    TokenMintedAt,
+    NonEmptyCollections,
+    TokensByOwnerInCollection,
+    TokensByOwnerInCollectionInner { owner_collection_hash: CryptoHash },
+    CollectionStats,
+    CollectionTags,
+    CollectionsByTag,
+    CollectionsByTagInner { tag_hash: CryptoHash },
+    CollectionsByTitlePrefix,
+    CollectionsByTitlePrefixInner { prefix_hash: CryptoHash },
+    Moderators,
+    CollectionModeration,
+    CollectionsByStatus,
+    CollectionsByStatusInner { status: CollectionStatus },
+    RebuiltCollectionTokens,
+    CollectionTransferPolicy,
+    TokenLockedUntil,
+    CollectionLockedUntil,
+    ApprovalConstraints,
+    OperatorApprovals,
+    OperatorApprovalsInner { account_id_hash: CryptoHash },
+    Listings,
+    ListingsByCollection,
+    ListingsByCollectionInner { collection_id_hash: CryptoHash },
+    Auctions,
+    AuctionsByCollection,
+    AuctionsByCollectionInner { collection_id_hash: CryptoHash },
+    Offers,
+    OffersByToken,
+    OffersByTokenInner { token_id_hash: CryptoHash },
+    OffersByCollection,
+    OffersByCollectionInner { collection_id_hash: CryptoHash },
+    FtTokens,
+    CollectionFtMintSales,
+    ListingFtTokens,
+    FtPendingPayouts,
+    LegacyCollectionTokens,
+    BurnedTokens,
+    CollectionMintStorageDeposits,
 }
 
 type CollectionId = String;
@@ -45,6 +115,25 @@ pub struct Payout {
     pub payout: HashMap<AccountId, U128>,
 }
 
+impl Payout {
+    /// Royalties leave room for the highest platform fee and a payout slot for the treasury.
+    pub fn assert_valid(&self) {
+        assert!(self.payout.len() < MAX_LEN_PAYOUT,
+                "Royalties can have at most {} receivers", MAX_LEN_PAYOUT - 1);
+        assert!(
+            self
+                .payout
+                .values()
+                .map(|value| u128::from(*value))
+                .sum::<u128>() <= MAX_PAYOUT - MAX_PLATFORM_FEE as u128,
+            "Royalties can't exceed {} basis points", MAX_PAYOUT - MAX_PLATFORM_FEE as u128);
+        assert!(self
+            .payout
+            .keys()
+            .all(|acc| is_valid_account_id(acc.as_bytes())));
+    }
+}
+
 #[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct CollectionMetadata {
@@ -73,6 +162,34 @@ pub struct CollectionsBatch {
     pub total_count: u64,
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CollectionInfo {
+    #[serde(flatten)]
+    pub metadata: CollectionMetadata,
+    pub transfer_policy: TransferPolicy,
+    pub locked_until: Option<U64>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OwnerCollection {
+    #[serde(flatten)]
+    pub metadata: CollectionMetadata,
+    /// Number of tokens in the collection, set only if it was requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supply: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CollectionsPage {
+    pub collections: Vec<CollectionMetadata>,
+    /// Opaque cursor of the next page, `None` if this page is the last one.
+    pub next_cursor: Option<String>,
+    pub total_count: u64,
+}
+
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
@@ -85,15 +202,54 @@ pub struct Contract {
     tokens_by_collection_id: LookupMap<CollectionId, Vector<TokenId>>,
     total_minted: u128,
     total_collections: u128,
+    fee_config: FeeConfig,
+    token_creators: LookupMap<TokenId, AccountId>,
+    token_minted_at: LookupMap<TokenId, u64>,
+    // collections with at least one token or hosted by another contract
+    non_empty_collections: UnorderedSet<CollectionId>,
+    tokens_by_owner_in_collection: LookupMap<(AccountId, CollectionId), UnorderedSet<TokenId>>,
+    collection_stats: LookupMap<CollectionId, CollectionStats>,
+    collection_tags: LookupMap<CollectionId, CollectionTags>,
+    collections_by_tag: LookupMap<String, UnorderedSet<CollectionId>>,
+    collections_by_title_prefix: LookupMap<String, UnorderedSet<CollectionId>>,
+    moderators: UnorderedSet<AccountId>,
+    collection_moderation: LookupMap<CollectionId, CollectionModeration>,
+    collections_by_status: LookupMap<CollectionStatus, UnorderedSet<CollectionId>>,
+    // collections whose token vectors were already rebuilt by the running rebuild,
+    // `None` when no rebuild is in progress
+    rebuilt_collection_tokens: Option<UnorderedSet<CollectionId>>,
+    collection_transfer_policy: LookupMap<CollectionId, TransferPolicy>,
+    token_locked_until: LookupMap<TokenId, u64>,
+    collection_locked_until: LookupMap<CollectionId, u64>,
+    approval_constraints: LookupMap<(TokenId, AccountId), ApprovalConstraints>,
+    operator_approvals: LookupMap<AccountId, UnorderedSet<OperatorApproval>>,
+    listings: UnorderedMap<TokenId, Listing>,
+    listings_by_collection: LookupMap<CollectionId, UnorderedSet<TokenId>>,
+    auctions: UnorderedMap<TokenId, Auction>,
+    auctions_by_collection: LookupMap<CollectionId, UnorderedSet<TokenId>>,
+    offers: UnorderedMap<OfferId, Offer>,
+    next_offer_id: OfferId,
+    offers_by_token: LookupMap<TokenId, UnorderedSet<OfferId>>,
+    offers_by_collection: LookupMap<CollectionId, UnorderedSet<OfferId>>,
+    ft_tokens: UnorderedSet<AccountId>,
+    collection_ft_mint_sales: LookupMap<CollectionId, FtMintSale>,
+    listing_ft_tokens: LookupMap<TokenId, AccountId>,
+    ft_pending_payouts: LookupMap<(AccountId, AccountId), u128>,
+    // owners and lengths of token vectors of the old layout left to clear by the rebuild
+    legacy_collection_tokens: Vector<(AccountId, u64)>,
+    // ids of burned tokens can't be minted again
+    burned_tokens: LookupSet<TokenId>,
+    // NEAR paid by collection owners for storage of tokens minted by mint sales
+    collection_mint_storage_deposits: LookupMap<CollectionId, u128>,
 }
 
 near_contract_standards::impl_non_fungible_token_enumeration!(Contract, tokens);
-near_contract_standards::impl_non_fungible_token_approval!(Contract, tokens);
 
 const MJOL_CONTRACT: &str = "mjol.near";
 
 const MAX_PAYOUT: u128 = 10_000u128;
 const MAX_LEN_PAYOUT: usize = 10;
+const MAX_PLATFORM_FEE: u32 = 1_000;
 
 const COLLECTION_TAG: &str = "collection";
 const TOKEN_TAG: &str = "token";
@@ -115,7 +271,7 @@ impl Contract {
         Self {
             tokens: NonFungibleToken::new(
                 StorageKey::NonFungibleToken,
-                owner_id,
+                owner_id.clone(),
                 Some(StorageKey::TokenMetadata),
                 Some(StorageKey::Enumeration),
                 Some(StorageKey::Approval),
@@ -127,9 +283,63 @@ impl Contract {
             tokens_by_collection_id: LookupMap::new(StorageKey::TokensByCollectionId),
             total_minted: 0,
             total_collections: 0,
+            fee_config: FeeConfig {
+                treasury_id: owner_id,
+                fee: 0,
+            },
+            token_creators: LookupMap::new(StorageKey::TokenCreators),
+            token_minted_at: LookupMap::new(StorageKey::TokenMintedAt),
+            non_empty_collections: UnorderedSet::new(StorageKey::NonEmptyCollections),
+            tokens_by_owner_in_collection: LookupMap::new(StorageKey::TokensByOwnerInCollection),
+            collection_stats: LookupMap::new(StorageKey::CollectionStats),
+            collection_tags: LookupMap::new(StorageKey::CollectionTags),
+            collections_by_tag: LookupMap::new(StorageKey::CollectionsByTag),
+            collections_by_title_prefix: LookupMap::new(StorageKey::CollectionsByTitlePrefix),
+            moderators: UnorderedSet::new(StorageKey::Moderators),
+            collection_moderation: LookupMap::new(StorageKey::CollectionModeration),
+            collections_by_status: LookupMap::new(StorageKey::CollectionsByStatus),
+            rebuilt_collection_tokens: None,
+            collection_transfer_policy: LookupMap::new(StorageKey::CollectionTransferPolicy),
+            token_locked_until: LookupMap::new(StorageKey::TokenLockedUntil),
+            collection_locked_until: LookupMap::new(StorageKey::CollectionLockedUntil),
+            approval_constraints: LookupMap::new(StorageKey::ApprovalConstraints),
+            operator_approvals: LookupMap::new(StorageKey::OperatorApprovals),
+            listings: UnorderedMap::new(StorageKey::Listings),
+            listings_by_collection: LookupMap::new(StorageKey::ListingsByCollection),
+            auctions: UnorderedMap::new(StorageKey::Auctions),
+            auctions_by_collection: LookupMap::new(StorageKey::AuctionsByCollection),
+            offers: UnorderedMap::new(StorageKey::Offers),
+            next_offer_id: 0,
+            offers_by_token: LookupMap::new(StorageKey::OffersByToken),
+            offers_by_collection: LookupMap::new(StorageKey::OffersByCollection),
+            ft_tokens: UnorderedSet::new(StorageKey::FtTokens),
+            collection_ft_mint_sales: LookupMap::new(StorageKey::CollectionFtMintSales),
+            listing_ft_tokens: LookupMap::new(StorageKey::ListingFtTokens),
+            ft_pending_payouts: LookupMap::new(StorageKey::FtPendingPayouts),
+            legacy_collection_tokens: Vector::new(StorageKey::LegacyCollectionTokens),
+            burned_tokens: LookupSet::new(StorageKey::BurnedTokens),
+            collection_mint_storage_deposits: LookupMap::new(StorageKey::CollectionMintStorageDeposits),
         }
     }
 
+    #[private]
+    pub fn set_fee_config(&mut self, treasury_id: AccountId, fee: u32) {
+        assert!(fee <= MAX_PLATFORM_FEE, "Platform fee can't exceed {} basis points", MAX_PLATFORM_FEE);
+        self.fee_config = FeeConfig {
+            treasury_id,
+            fee,
+        };
+
+        env::log_str(&json!({
+            "type": "set_fee_config",
+            "data": self.fee_config.clone()
+        }).to_string())
+    }
+
+    pub fn get_fee_config(&self) -> FeeConfig {
+        self.fee_config.clone()
+    }
+
     #[payable]
     #[private]
     pub fn add_collection(&mut self,
@@ -146,7 +356,14 @@ impl Contract {
                              collection_id: ContractId,
                              owner_id: AccountId,
     ) {
-        self.collections.remove(&collection_id.clone());
+        if let Some(metadata) = self.collections.remove(&collection_id.clone()) {
+            self.internal_unindex_collection_search(&metadata);
+            self.internal_remove_collection_ft_mint_sale(&collection_id, metadata.owner_id);
+        }
+        self.non_empty_collections.remove(&collection_id);
+        self.internal_clear_collection_status(&collection_id);
+        self.collection_transfer_policy.remove(&collection_id);
+        self.collection_locked_until.remove(&collection_id);
         let mut collections = self.collections_by_owner_id.get(&owner_id.clone()).unwrap();
         collections.remove(&collection_id.clone());
         self.collections_by_owner_id.insert(&owner_id.clone(), &collections);
@@ -186,14 +403,20 @@ impl Contract {
         contract_id: ContractId,
         owner_id: AccountId,
     ) -> CollectionMetadata {
+        // storage of the collection and its search index is paid from the attached deposit
+        let initial_storage_usage = env::storage_usage();
         let collection_id: CollectionId = if let Some(id) = metadata.custom_collection_id {
-            let url_regexp = Regex::new(r"^[a-z\-\d]+$").unwrap();
-            assert!(url_regexp.is_match(&id));
-            assert!(self.collections.get(&id).is_none());
+            assert_valid_collection_slug(&id);
+            assert!(self.collections.get(&id).is_none(), "Collection id is already taken: {}", id);
             id
         } else {
-            let new_id = self.next_collection();
-            format!("{}{}{}", COLLECTION_TAG, DELIMITER, new_id)
+            // custom ids created before the prefix was reserved can take generated ones
+            loop {
+                let new_id = format!("{}{}{}", COLLECTION_TAG, DELIMITER, self.next_collection());
+                if self.collections.get(&new_id).is_none() {
+                    break new_id;
+                }
+            }
         };
 
         assert!(self.collections.get(&collection_id.clone()).is_none());
@@ -207,6 +430,18 @@ impl Contract {
             reference: metadata.reference,
         };
         assert!(self.collections.insert(&collection_id.clone(), &meta.clone()).is_none());
+        self.internal_index_collection_search(&meta,
+                                              metadata.tags.unwrap_or_default(),
+                                              metadata.category);
+        // written even when default, so nothing is left from a removed collection with this id
+        self.collection_transfer_policy.insert(&collection_id, &metadata.transfer_policy.unwrap_or_default());
+        match metadata.locked_until {
+            Some(locked_until) => self.collection_locked_until.insert(&collection_id, &locked_until.0),
+            None => self.collection_locked_until.remove(&collection_id),
+        };
+        if contract_id != MJOL_CONTRACT {
+            self.non_empty_collections.insert(&collection_id);
+        }
 
         let mut owners_collections = self
             .collections_by_owner_id
@@ -217,6 +452,7 @@ impl Contract {
                 }.try_to_vec().unwrap()));
         assert!(owners_collections.insert(&collection_id.clone()));
         self.collections_by_owner_id.insert(&owner_id.clone(), &owners_collections);
+        refund_storage_deposit(env::storage_usage() - initial_storage_usage);
 
         env::log_str(&json!({
             "type": "create_collection",
@@ -233,15 +469,25 @@ impl Contract {
         mut token_metadata: TokenMetadata,
         payout: Option<Payout>,
         collection_id: Option<CollectionId>,
+        token_id: Option<TokenId>,
+        locked_until: Option<U64>,
     ) {
+        // storage of all copies, royalties and collection index is paid at once
+        let initial_storage_usage = env::storage_usage();
         if collection_id.is_some() && token_metadata.copies.is_some() {
             assert_eq!(token_metadata.copies.unwrap(), 1,
                        "Every collection can have only one copy of NFT.")
         }
-        let new_token_id = self.next_token();
-        let token_id = format!("{}{}{}", TOKEN_TAG, DELIMITER, new_token_id);
+        let token_id = if let Some(id) = token_id {
+            assert_valid_token_id(&id);
+            self.assert_token_id_unused(&id);
+            id
+        } else {
+            let new_token_id = self.next_token();
+            format!("{}{}{}", TOKEN_TAG, DELIMITER, new_token_id)
+        };
 
-        if let Some(some_collection_id) = collection_id {
+        if let Some(some_collection_id) = collection_id.clone() {
             let collection_owner = env::predecessor_account_id();
             assert!(self.collections.get(&some_collection_id.clone()).is_some());
 
@@ -254,19 +500,15 @@ impl Contract {
                        collection_owner,
                        "Only owner of collection can mint to collection");
 
-            let mut collection_tokens = self
-                .tokens_by_collection_id
-                .get(&some_collection_id.clone())
-                .unwrap_or_else(||
-                    Vector::new(StorageKey::TokensByCollectionIdInner {
-                        account_id_hash: hash_account_id(&collection_owner.clone())
-                    }.try_to_vec().unwrap()));
+            self.assert_collection_tokens_not_rebuilding();
+            let mut collection_tokens = self.internal_collection_tokens(&some_collection_id);
 
             collection_tokens.push(&token_id.clone());
 
             self
                 .tokens_by_collection_id
                 .insert(&some_collection_id.clone(), &collection_tokens);
+            self.non_empty_collections.insert(&some_collection_id);
 
             let extra = json!({
                     "collection_id": collection_metadata.collection_id,
@@ -276,102 +518,86 @@ impl Contract {
             token_metadata.extra = Some(extra.to_string())
         }
 
-        if let Some(royalties) = payout {
-            assert!(royalties.payout.len() <= MAX_LEN_PAYOUT);
-            assert!(
-                royalties
-                    .payout
-                    .values()
-                    .map(|value| u128::from(*value))
-                    .sum::<u128>() < MAX_PAYOUT);
-            assert!(royalties
-                .payout
-                .keys()
-                .all(|acc| is_valid_account_id(acc.as_bytes())));
+        let minted_ids = if let Some(royalties) = payout {
+            royalties.assert_valid();
 
-            self.mint_tokens(token_id,
-                             token_owner_id,
+            self.mint_tokens(token_id.clone(),
+                             token_owner_id.clone(),
                              token_metadata,
-                             Some(royalties.clone()));
+                             Some(royalties.clone()))
         } else {
             // no royalties mint
-            self.mint_tokens(token_id,
-                             token_owner_id,
+            self.mint_tokens(token_id.clone(),
+                             token_owner_id.clone(),
                              token_metadata,
-                             None);
+                             None)
+        };
+
+        if let Some(locked_until) = locked_until {
+            for minted_id in minted_ids.iter() {
+                self.token_locked_until.insert(minted_id, &locked_until.0);
+            }
+        }
+
+        if let Some(collection_id) = collection_id {
+            self.internal_add_token_to_owner_in_collection(&token_owner_id, &collection_id, &token_id);
+            self.internal_update_collection_stats(&collection_id, |stats| stats.minted += 1);
         }
+
+        refund_storage_deposit(env::storage_usage() - initial_storage_usage);
     }
 
     fn mint_tokens(&mut self, token_id: TokenId,
                    token_owner_id: AccountId,
                    mut token_metadata: TokenMetadata,
                    maybe_royalties: Option<Payout>,
-    ) {
-        let token_title = token_metadata.title.clone().unwrap();
+    ) -> Vec<TokenId> {
         let mut minted_ids = vec![];
         match token_metadata.copies {
             Some(1) | None => {
-                self
-                    .tokens
-                    .internal_mint(token_id.clone(),
-                                   token_owner_id.clone(),
-                                   Some(token_metadata));
-                maybe_royalties.clone().map(|royalties|
-                    self
-                        .payouts
-                        .insert(&token_id, &royalties));
+                assert_enough_gas_for_mint(1);
+                self.internal_mint_token(token_id.clone(),
+                                         token_owner_id.clone(),
+                                         token_metadata,
+                                         maybe_royalties);
                 minted_ids.push(token_id.clone());
             }
             Some(copies) => {
+                assert_enough_gas_for_mint(copies);
+                let token_title = token_metadata.title.clone().unwrap();
                 for copy_id in 1..(copies + 1) {
                     token_metadata.title = Some(format!("{}{}{}", token_title.clone(), COPY_NAME_DELIMITER, copy_id));
                     let copy_token_id =
                         format!("{}{}{}", token_id.clone(), COPY_DELIMITER, copy_id);
-                    let refund = if copy_id != copies - 1 {
-                        None
-                    } else {
-                        Some(env::predecessor_account_id())
-                    };
-                    self
-                        .tokens
-                        .internal_mint_with_refund(copy_token_id.clone(),
-                                                   token_owner_id.clone(),
-                                                   Some(token_metadata.clone()),
-                                                   refund);
-                    maybe_royalties.clone().map(|royalties|
-                        self
-                            .payouts
-                            .insert(&copy_token_id, &royalties));
+                    self.internal_mint_token(copy_token_id.clone(),
+                                             token_owner_id.clone(),
+                                             token_metadata.clone(),
+                                             maybe_royalties.clone());
                     minted_ids.push(copy_token_id.clone());
                 }
-
-                env::log_str(
-                    &format!("EVENT_JSON:{}", json!({
-                    "standard": "nep171",
-                    "version": "1.0.0",
-                    "event": "nft_mint",
-                    "data": [
-                        {
-                            "owner_id": token_owner_id,
-                            "token_ids": minted_ids
-                        }
-                    ]
-                }).to_string()));
             }
         }
+
+        env::log_str(
+            &format!("EVENT_JSON:{}", json!({
+            "standard": "nep171",
+            "version": "1.0.0",
+            "event": "nft_mint",
+            "data": [
+                {
+                    "owner_id": token_owner_id,
+                    "token_ids": minted_ids
+                }
+            ]
+        }).to_string()));
+
+        minted_ids
     }
 
     pub fn get_nfts_from_collection(&self, collection_id: CollectionId,
                                     limit: u64, from: u64) -> CollectionData {
         assert!(self.collections.get(&collection_id.clone()).is_some());
-        let collection_owner = self.collections.get(&collection_id.clone()).unwrap().owner_id;
-        let token_ids = self
-            .tokens_by_collection_id
-            .get(&collection_id.clone())
-            .unwrap_or_else(||
-                Vector::new(StorageKey::TokensByCollectionIdInner {
-                    account_id_hash: hash_account_id(&collection_owner.clone())
-                }.try_to_vec().unwrap()));
+        let token_ids = self.internal_collection_tokens(&collection_id);
         let size = token_ids.len() as u64;
 
         let mut res = vec![];
@@ -386,9 +612,10 @@ impl Contract {
         let real_from = max(real_to as i64 - limit as i64, 0 as i64) as usize;
 
         for i in (real_from..real_to).rev() {
-            res.push(self
+            // burned tokens are skipped
+            res.extend(self
                 .tokens
-                .nft_token(token_ids.get(i as u64).unwrap()).unwrap())
+                .nft_token(token_ids.get(i as u64).unwrap()))
         }
         CollectionData {
             tokens: res,
@@ -401,17 +628,25 @@ impl Contract {
         self.metadata
     }
 
-    pub fn get_collections(&self, limit: u64, from: u64, include_empty: bool) -> CollectionsBatch {
-        let collections: Vec<CollectionMetadata>;
-        if include_empty {
-            collections = self.collections.values().collect();
-        } else {
-            collections = self.collections.values().filter(|metadata|
-                self.tokens_by_collection_id.get(&metadata.collection_id).is_some() ||
-                    metadata.collection_contract != std::string::String::from("mjol.near")).collect();
+    /// With `include_empty` newest created collections first. Otherwise collections
+    /// come from the non-empty index, latest first by the time they got their first
+    /// token; removing a collection moves the last indexed one into its place.
+    /// With `status` set only collections with this moderation status are returned,
+    /// oldest moderated first. Hidden collections are returned only when asked for by
+    /// `status`, so pages may hold less than `limit` collections.
+    pub fn get_collections(&self,
+                           limit: u64,
+                           from: u64,
+                           include_empty: bool,
+                           status: Option<CollectionStatus>) -> CollectionsBatch {
+        if let Some(status) = status {
+            return self.internal_collections_batch(self.collections_by_status.get(&status),
+                                                   from,
+                                                   limit,
+                                                   |metadata| include_empty ||
+                                                       self.non_empty_collections.contains(&metadata.collection_id));
         }
-
-        let size = collections.len() as u64;
+        let size = self.internal_collections_len(include_empty);
 
         let mut res = vec![];
         if from >= size {
@@ -421,11 +656,13 @@ impl Contract {
                 total_count: size,
             };
         }
-        let real_to = (size - from) as usize;
-        let real_from = max(real_to as i64 - limit as i64, 0 as i64) as usize;
+        let real_to = size - from;
+        let real_from = max(real_to as i64 - limit as i64, 0 as i64) as u64;
 
         for i in (real_from..real_to).rev() {
-            res.push(collections[i].clone())
+            res.extend(self
+                .internal_collection_at(i, include_empty)
+                .filter(|metadata| !self.internal_is_collection_hidden(&metadata.collection_id)))
         }
         CollectionsBatch {
             collections: res,
@@ -434,20 +671,124 @@ impl Contract {
         }
     }
 
-    pub fn get_collection_info(&self, collection_id: CollectionId) -> Option<CollectionMetadata> {
-        self.collections.get(&collection_id)
+    /// Pages over collections in storage order, or from the end if `reverse` is set.
+    /// Storage order is the creation order with `include_empty`, otherwise the order
+    /// of the non-empty index described in `get_collections`. Hidden collections are skipped.
+    /// `cursor` is the `next_cursor` of the previous page, `None` for the first page.
+    pub fn get_collections_page(&self,
+                                cursor: Option<String>,
+                                limit: u64,
+                                reverse: bool,
+                                include_empty: bool) -> CollectionsPage {
+        let size = self.internal_collections_len(include_empty);
+        let start: u64 = match cursor {
+            Some(cursor) => cursor.parse().expect("Error: invalid cursor."),
+            None if reverse => size,
+            None => 0,
+        };
+        let indices: Vec<u64> = if reverse {
+            let start = std::cmp::min(start, size);
+            (start.saturating_sub(limit)..start).rev().collect()
+        } else {
+            (start..std::cmp::min(start.saturating_add(limit), size)).collect()
+        };
+
+        let next_cursor = match indices.last() {
+            Some(&last) if reverse && last > 0 => Some(last.to_string()),
+            Some(&last) if !reverse && last + 1 < size => Some((last + 1).to_string()),
+            _ => None,
+        };
+        CollectionsPage {
+            collections: indices
+                .into_iter()
+                .filter_map(|i| self.internal_collection_at(i, include_empty))
+                .filter(|metadata| !self.internal_is_collection_hidden(&metadata.collection_id))
+                .collect(),
+            next_cursor,
+            total_count: size,
+        }
+    }
+
+    /// Fills index of non-empty collections for collections created before it existed.
+    #[private]
+    pub fn index_non_empty_collections(&mut self, from: u64, limit: u64) -> u64 {
+        let collection_ids = self.collections.keys_as_vector();
+        let to = std::cmp::min(from.saturating_add(limit), collection_ids.len());
+        for i in from..to {
+            let collection_id = collection_ids.get(i).unwrap();
+            let collection = self.collections.get(&collection_id).unwrap();
+            if self.tokens_by_collection_id.get(&collection_id).is_some() ||
+                collection.collection_contract != MJOL_CONTRACT {
+                self.non_empty_collections.insert(&collection_id);
+            }
+        }
+        to
+    }
+
+    fn internal_collections_len(&self, include_empty: bool) -> u64 {
+        if include_empty {
+            self.collections.len()
+        } else {
+            self.non_empty_collections.len()
+        }
+    }
+
+    fn internal_collection_at(&self, index: u64, include_empty: bool) -> Option<CollectionMetadata> {
+        if include_empty {
+            self.collections.values_as_vector().get(index)
+        } else {
+            self
+                .non_empty_collections
+                .as_vector()
+                .get(index)
+                .and_then(|collection_id| self.collections.get(&collection_id))
+        }
+    }
+
+    pub fn get_collection_info(&self, collection_id: CollectionId) -> Option<CollectionInfo> {
+        self.collections.get(&collection_id).map(|metadata| CollectionInfo {
+            transfer_policy: self.internal_collection_transfer_policy(&collection_id),
+            locked_until: self.collection_locked_until.get(&collection_id).map(U64),
+            metadata,
+        })
     }
 
-    pub fn get_collections_by_owner_id(&self, owner_id: AccountId) -> Vec<CollectionMetadata> {
+    pub fn get_collections_by_owner_id(&self,
+                                       owner_id: AccountId,
+                                       from: Option<u64>,
+                                       limit: Option<u64>,
+                                       include_supply: Option<bool>) -> Vec<OwnerCollection> {
+        let include_supply = include_supply.unwrap_or(false);
         self
             .collections_by_owner_id
             .get(&owner_id.clone())
             .map(|x|
-                x.to_vec().iter().map(|collection_id|
-                    self.collections.get(collection_id).unwrap()).collect())
+                x
+                    .iter()
+                    .skip(from.unwrap_or(0) as usize)
+                    .take(limit.unwrap_or(u64::MAX) as usize)
+                    // ids left behind by remove_collection are skipped
+                    .filter_map(|collection_id| self.collections.get(&collection_id))
+                    .map(|metadata| OwnerCollection {
+                        supply: if include_supply {
+                            Some(self.internal_collection_supply(&metadata.collection_id))
+                        } else {
+                            None
+                        },
+                        metadata,
+                    })
+                    .collect())
             .unwrap_or_else(|| vec![])
     }
 
+    pub fn get_collections_by_owner_id_count(&self, owner_id: AccountId) -> u64 {
+        self
+            .collections_by_owner_id
+            .get(&owner_id)
+            .map(|collections| collections.len())
+            .unwrap_or(0)
+    }
+
     pub fn nft_royalties(&self, token_id: TokenId, max_len_payout: u32) -> HashMap<AccountId, U128> {
         let royalties: HashMap<AccountId, U128> = self
             .payouts
@@ -459,7 +800,28 @@ impl Contract {
     }
 
     pub fn nft_collection_supply(&self, collection_id: CollectionId) -> String {
-        return self.tokens_by_collection_id.get(&collection_id).unwrap_or(Vector::new(b"v".to_vec())).len().to_string();
+        return self.internal_collection_supply(&collection_id).to_string();
+    }
+
+    fn internal_collection_tokens(&self, collection_id: &CollectionId) -> Vector<TokenId> {
+        self
+            .tokens_by_collection_id
+            .get(collection_id)
+            .unwrap_or_else(|| new_collection_tokens(collection_id))
+    }
+
+    /// Burned ids stay in the collection token vector, so they're subtracted from its length.
+    fn internal_collection_supply(&self, collection_id: &CollectionId) -> u64 {
+        let burned = self
+            .collection_stats
+            .get(collection_id)
+            .map(|stats| stats.burned)
+            .unwrap_or(0);
+        self
+            .tokens_by_collection_id
+            .get(collection_id)
+            .map(|tokens| tokens.len().saturating_sub(burned))
+            .unwrap_or(0)
     }
 
     fn next_collection(&mut self) -> u128 {
@@ -474,6 +836,53 @@ impl Contract {
         res
     }
 
+    /// Transfer shared by `nft_transfer` and `nft_transfer_payout`, `price` is the sale
+    /// price checked against the approval, `None` for transfers without a sale.
+    fn internal_nft_transfer(&mut self,
+                             receiver_id: AccountId,
+                             token_id: TokenId,
+                             approval_id: Option<u64>,
+                             memo: Option<String>,
+                             price: Option<U128>) {
+        let old_owner_id = self
+            .tokens.owner_by_id
+            .get(&token_id)
+            .expect("Error: no such token id.");
+        self.assert_token_transferable(&token_id);
+        self.assert_token_unlocked(&token_id);
+        self.assert_token_has_no_bids(&token_id);
+        self.assert_approval_constraints(&token_id, price);
+        let approved_ids = self.internal_token_approvals(&token_id).into_keys().collect();
+        self.internal_refund_approval_constraints(&token_id, &old_owner_id, approved_ids);
+        if self.internal_is_operator(&old_owner_id, &env::predecessor_account_id(), &token_id) {
+            assert_one_yocto();
+            self.tokens.internal_transfer(&old_owner_id, &receiver_id, &token_id, None, memo.clone());
+        } else {
+            self.tokens.nft_transfer(receiver_id.clone(),
+                                     token_id.clone(),
+                                     approval_id,
+                                     memo.clone());
+        }
+        self.internal_on_token_moved(&token_id, &old_owner_id, &receiver_id, true);
+        self.internal_remove_listing(&token_id);
+        self.internal_cancel_auction(&token_id);
+        env::log_str(&json!({
+        "standard": "nep171",
+        "version": "1.0.0",
+        "event": "nft_transfer",
+        "data": [
+                {
+                    "authorized_id": approval_id,
+                    "old_owner_id": env::predecessor_account_id(),
+                    "new_owner_id": receiver_id,
+                    "token_ids": [token_id],
+                    "memo": memo
+                }
+            ]
+        }).to_string());
+    }
+
+    /// Migrates the state of the contract before marketplace features were added.
     #[init(ignore_state)]
     #[private]
     pub fn migrate() -> Self {
@@ -490,6 +899,7 @@ impl Contract {
         }
 
         let prev_state: Old = env::state_read().expect("No such state.");
+        let owner_id = prev_state.tokens.owner_id.clone();
 
         Self {
             metadata: prev_state.metadata,
@@ -500,13 +910,70 @@ impl Contract {
             tokens_by_collection_id: prev_state.tokens_by_collection_id,
             total_minted: prev_state.total_minted,
             total_collections: prev_state.total_collections,
+            fee_config: FeeConfig {
+                treasury_id: owner_id,
+                fee: 0,
+            },
+            token_creators: LookupMap::new(StorageKey::TokenCreators),
+            token_minted_at: LookupMap::new(StorageKey::TokenMintedAt),
+            non_empty_collections: UnorderedSet::new(StorageKey::NonEmptyCollections),
+            tokens_by_owner_in_collection: LookupMap::new(StorageKey::TokensByOwnerInCollection),
+            collection_stats: LookupMap::new(StorageKey::CollectionStats),
+            collection_tags: LookupMap::new(StorageKey::CollectionTags),
+            collections_by_tag: LookupMap::new(StorageKey::CollectionsByTag),
+            collections_by_title_prefix: LookupMap::new(StorageKey::CollectionsByTitlePrefix),
+            moderators: UnorderedSet::new(StorageKey::Moderators),
+            collection_moderation: LookupMap::new(StorageKey::CollectionModeration),
+            collections_by_status: LookupMap::new(StorageKey::CollectionsByStatus),
+            // token vectors of collections with the same owner shared one prefix
+            rebuilt_collection_tokens: Some(UnorderedSet::new(StorageKey::RebuiltCollectionTokens)),
+            collection_transfer_policy: LookupMap::new(StorageKey::CollectionTransferPolicy),
+            token_locked_until: LookupMap::new(StorageKey::TokenLockedUntil),
+            collection_locked_until: LookupMap::new(StorageKey::CollectionLockedUntil),
+            approval_constraints: LookupMap::new(StorageKey::ApprovalConstraints),
+            operator_approvals: LookupMap::new(StorageKey::OperatorApprovals),
+            listings: UnorderedMap::new(StorageKey::Listings),
+            listings_by_collection: LookupMap::new(StorageKey::ListingsByCollection),
+            auctions: UnorderedMap::new(StorageKey::Auctions),
+            auctions_by_collection: LookupMap::new(StorageKey::AuctionsByCollection),
+            offers: UnorderedMap::new(StorageKey::Offers),
+            next_offer_id: 0,
+            offers_by_token: LookupMap::new(StorageKey::OffersByToken),
+            offers_by_collection: LookupMap::new(StorageKey::OffersByCollection),
+            ft_tokens: UnorderedSet::new(StorageKey::FtTokens),
+            collection_ft_mint_sales: LookupMap::new(StorageKey::CollectionFtMintSales),
+            listing_ft_tokens: LookupMap::new(StorageKey::ListingFtTokens),
+            ft_pending_payouts: LookupMap::new(StorageKey::FtPendingPayouts),
+            legacy_collection_tokens: Vector::new(StorageKey::LegacyCollectionTokens),
+            burned_tokens: LookupSet::new(StorageKey::BurnedTokens),
+            collection_mint_storage_deposits: LookupMap::new(StorageKey::CollectionMintStorageDeposits),
         }
     }
 }
 
 
+/// Token vector of the collection, prefixed by the collection id so collections
+/// of the same owner don't share storage.
+fn new_collection_tokens(collection_id: &CollectionId) -> Vector<TokenId> {
+    Vector::new(StorageKey::TokensByCollectionIdInner {
+        collection_id_hash: hash_string(collection_id)
+    }.try_to_vec().unwrap())
+}
+
+fn hash_string(value: &str) -> CryptoHash {
+    hash_bytes(value.as_bytes())
+}
+
+fn hash_bytes(value: &[u8]) -> CryptoHash {
+    let mut hash = CryptoHash::default();
+    hash.copy_from_slice(&env::sha256(value));
+    hash
+}
+
+/// Returns `a / MAX_PAYOUT` share of `b` rounded down. The balance is split into
+/// quotient and remainder by `MAX_PAYOUT` first, so `a * b` never overflows.
 fn payout_part_from_balance(a: u128, b: u128) -> U128 {
-    U128(a * b / MAX_PAYOUT)
+    U128(b / MAX_PAYOUT * a + b % MAX_PAYOUT * a / MAX_PAYOUT)
 }
 
 #[near_bindgen]
@@ -541,12 +1008,26 @@ impl Payouts for Contract {
                 }
             ).collect();
 
-        assert!(payouts.len() <= max_len_payout as usize);
         assert!(total_royalties < MAX_PAYOUT);
 
-        payouts
-            .insert(owner_id,
-                    payout_part_from_balance(MAX_PAYOUT - total_royalties, balance_u128));
+        // the fee comes out of the owner's share, so tokens minted with royalties
+        // above the cap stay sellable once the fee is turned on
+        let platform_fee = self.fee_config.fee as u128;
+        if platform_fee > 0 && self.fee_config.treasury_id != owner_id {
+            let royalties_paid: u128 = payouts.values().map(|value| u128::from(*value)).sum();
+            let fee_amount = std::cmp::min(u128::from(payout_part_from_balance(platform_fee, balance_u128)),
+                                           balance_u128 - royalties_paid);
+            let treasury_amount = payouts
+                .entry(self.fee_config.treasury_id.clone())
+                .or_insert(U128(0));
+            treasury_amount.0 += fee_amount;
+        }
+
+        assert!(payouts.len() <= max_len_payout as usize);
+
+        // owner receives everything that's left, including rounding dust
+        let paid: u128 = payouts.values().map(|value| u128::from(*value)).sum();
+        payouts.insert(owner_id, U128(balance_u128 - paid));
 
         Payout { payout: payouts }
     }
@@ -560,7 +1041,7 @@ impl Payouts for Contract {
                            max_len_payout: u32) -> Payout {
         assert_one_yocto();
         let payout = self.nft_payout(token_id.clone(), balance, max_len_payout);
-        self.nft_transfer(receiver_id, token_id, Some(approval_id), None);
+        self.internal_nft_transfer(receiver_id, token_id, Some(approval_id), None, Some(balance));
         payout
     }
 }
@@ -575,24 +1056,7 @@ impl NonFungibleTokenCore for Contract {
         approval_id: Option<u64>,
         memo: Option<String>,
     ) {
-        self.tokens.nft_transfer(receiver_id.clone(),
-                                 token_id.clone(),
-                                 approval_id.clone(),
-                                 memo.clone());
-        env::log_str(&json!({
-        "standard": "nep171",
-        "version": "1.0.0",
-        "event": "nft_transfer",
-        "data": [
-                {
-                    "authorized_id": approval_id,
-                    "old_owner_id": env::predecessor_account_id(),
-                    "new_owner_id": receiver_id,
-                    "token_ids": [token_id],
-                    "memo": memo
-                }
-            ]
-        }).to_string());
+        self.internal_nft_transfer(receiver_id, token_id, approval_id, memo, None);
     }
 
     #[payable]
@@ -604,6 +1068,20 @@ impl NonFungibleTokenCore for Contract {
         memo: Option<String>,
         msg: String,
     ) -> PromiseOrValue<bool> {
+        let old_owner_id = self
+            .tokens.owner_by_id
+            .get(&token_id)
+            .expect("Error: no such token id.");
+        self.assert_token_transferable(&token_id);
+        self.assert_token_unlocked(&token_id);
+        self.assert_token_has_no_bids(&token_id);
+        self.assert_approval_constraints(&token_id, None);
+        self.internal_on_token_moved(&token_id, &old_owner_id, &receiver_id, true);
+        self.internal_remove_listing(&token_id);
+        self.internal_cancel_auction(&token_id);
+        if self.internal_is_operator(&old_owner_id, &env::predecessor_account_id(), &token_id) {
+            return self.internal_operator_transfer_call(old_owner_id, receiver_id, token_id, memo, msg);
+        }
         self.tokens.nft_transfer_call(receiver_id, token_id, approval_id, memo, msg)
     }
 
@@ -622,11 +1100,414 @@ impl NonFungibleTokenResolver for Contract {
         token_id: TokenId,
         approved_account_ids: Option<std::collections::HashMap<AccountId, u64>>,
     ) -> bool {
-        self.tokens.nft_resolve_transfer(
-            previous_owner_id,
-            receiver_id,
-            token_id,
+        // constraints outlive `nft_transfer_call` as approvals come back with a returned token
+        let approved_ids: Vec<AccountId> = approved_account_ids
+            .as_ref()
+            .map(|approvals| approvals.keys().cloned().collect())
+            .unwrap_or_default();
+        let transferred = self.tokens.nft_resolve_transfer(
+            previous_owner_id.clone(),
+            receiver_id.clone(),
+            token_id.clone(),
             approved_account_ids,
-        )
+        );
+        if !transferred {
+            // token was returned to the previous owner
+            self.internal_on_token_moved(&token_id, &receiver_id, &previous_owner_id, false);
+        } else {
+            self.internal_refund_approval_constraints(&token_id, &previous_owner_id, approved_ids);
+        }
+        transferred
+    }
+}
+#[cfg(test)]
+mod tests {
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
+    use near_sdk::{testing_env, Gas, ONE_NEAR};
+
+    use super::*;
+
+    pub(crate) fn contract_id() -> AccountId {
+        MJOL_CONTRACT.parse().unwrap()
     }
-}
\ No newline at end of file
+
+    pub(crate) fn context(predecessor_id: AccountId, attached_deposit: u128) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(contract_id())
+            .predecessor_account_id(predecessor_id)
+            .attached_deposit(attached_deposit)
+            .prepaid_gas(Gas(300_000_000_000_000));
+        builder
+    }
+
+    pub(crate) fn setup_contract() -> Contract {
+        testing_env!(context(contract_id(), 0).build());
+        Contract::new(contract_id(), NFTContractMetadata {
+            spec: "nft-1.0.0".to_string(),
+            name: "Mjolnear".to_string(),
+            symbol: "MJOL".to_string(),
+            icon: None,
+            base_uri: None,
+            reference: None,
+            reference_hash: None,
+        })
+    }
+
+    pub(crate) fn token_metadata(copies: Option<u64>) -> TokenMetadata {
+        TokenMetadata {
+            title: Some("Token".to_string()),
+            description: None,
+            media: None,
+            media_hash: None,
+            copies,
+            issued_at: None,
+            expires_at: None,
+            starts_at: None,
+            updated_at: None,
+            extra: None,
+            reference: None,
+            reference_hash: None,
+        }
+    }
+
+    /// NEAR transfers made by the last call.
+    pub(crate) fn transfers() -> Vec<(AccountId, u128)> {
+        get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| {
+                let receiver_id = receipt.receiver_id;
+                receipt.actions.into_iter().filter_map(move |action| match action {
+                    VmAction::Transfer { deposit } => Some((receiver_id.clone(), deposit)),
+                    _ => None,
+                })
+            })
+            .collect()
+    }
+
+    /// xorshift64, deterministic so failures can be reproduced
+    fn next_random(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    fn random_balance(state: &mut u64) -> u128 {
+        let value = (next_random(state) as u128) << 64 | next_random(state) as u128;
+        value >> (next_random(state) % 128)
+    }
+
+    fn payout_sum(payout: &Payout) -> u128 {
+        payout.payout.values().map(|value| value.0).sum()
+    }
+
+    #[test]
+    fn payout_sums_to_balance() {
+        let mut contract = setup_contract();
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        for round in 0..50 {
+            let fee = (next_random(&mut state) % (MAX_PLATFORM_FEE as u64 + 1)) as u32;
+            testing_env!(context(contract_id(), 0).build());
+            contract.set_fee_config(accounts(5), fee);
+
+            let mut royalties = HashMap::new();
+            let mut left = MAX_PAYOUT - MAX_PLATFORM_FEE as u128;
+            for i in 0..(next_random(&mut state) % MAX_LEN_PAYOUT as u64) {
+                let share = next_random(&mut state) as u128 % (left + 1);
+                left -= share;
+                royalties.insert(format!("royalty{}.near", i).parse().unwrap(), U128(share));
+            }
+            testing_env!(context(accounts(0), ONE_NEAR).build());
+            contract.nft_mint(accounts(1),
+                              token_metadata(None),
+                              Some(Payout { payout: royalties.clone() }),
+                              None,
+                              Some(format!("payout-{}", round)),
+                              None);
+
+            let mut balances = vec![0, 1, MAX_PAYOUT - 1, MAX_PAYOUT + 1, u128::MAX - 1, u128::MAX];
+            balances.extend((0..20).map(|_| random_balance(&mut state)));
+            for balance in balances {
+                let payout = contract.nft_payout(format!("payout-{}", round),
+                                                 U128(balance),
+                                                 MAX_LEN_PAYOUT as u32 + 1);
+                assert_eq!(payout_sum(&payout), balance);
+                for (account_id, royalty) in royalties.iter() {
+                    assert_eq!(payout.payout[account_id].0,
+                               payout_part_from_balance(royalty.0, balance).0);
+                }
+                let treasury_amount = payout.payout.get(&accounts(5)).map(|value| value.0).unwrap_or(0);
+                assert!(treasury_amount <= payout_part_from_balance(fee as u128, balance).0);
+            }
+        }
+    }
+
+    #[test]
+    fn payout_split_is_exact_near_u128_max() {
+        for share in [1, 2_500, MAX_PAYOUT - 1] {
+            for balance in [u128::MAX, u128::MAX - 1, u128::MAX / 3, u128::MAX - MAX_PAYOUT] {
+                let part = payout_part_from_balance(share, balance).0;
+                let rest = payout_part_from_balance(MAX_PAYOUT - share, balance).0;
+                assert!(part <= balance - rest);
+                assert!(balance - part - rest <= 1);
+            }
+        }
+    }
+
+    #[test]
+    fn fee_is_taken_from_owner_share_of_legacy_royalties() {
+        let mut contract = setup_contract();
+        testing_env!(context(contract_id(), 0).build());
+        contract.set_fee_config(accounts(5), MAX_PLATFORM_FEE);
+        testing_env!(context(accounts(0), ONE_NEAR).build());
+        contract.nft_mint(accounts(1), token_metadata(None), None, None, Some("legacy".to_string()), None);
+        // royalties set before the cap existed
+        let mut royalties = HashMap::new();
+        royalties.insert(accounts(2), U128(MAX_PAYOUT - 1));
+        contract.payouts.insert(&"legacy".to_string(), &Payout { payout: royalties });
+
+        for balance in [0, 9_999, 10_000, 123_456_789, u128::MAX] {
+            let payout = contract.nft_payout("legacy".to_string(), U128(balance), MAX_LEN_PAYOUT as u32);
+            assert_eq!(payout_sum(&payout), balance);
+            assert_eq!(payout.payout[&accounts(2)].0, payout_part_from_balance(MAX_PAYOUT - 1, balance).0);
+        }
+    }
+
+    #[test]
+    fn mint_copies_refunds_once() {
+        let mut contract = setup_contract();
+        for (i, copies) in [2u64, 3, 50].iter().enumerate() {
+            testing_env!(context(accounts(0), 10 * ONE_NEAR).build());
+            contract.nft_mint(accounts(1), token_metadata(Some(*copies)), None, None, None, None);
+
+            let token_id = format!("{}{}{}", TOKEN_TAG, DELIMITER, i + 1);
+            for copy_id in 1..(copies + 1) {
+                let copy_token_id = format!("{}{}{}", token_id, COPY_DELIMITER, copy_id);
+                let token = contract.nft_token(copy_token_id).expect("copy is minted");
+                assert_eq!(token.owner_id, accounts(1));
+                assert_eq!(token.metadata.unwrap().title.unwrap(),
+                           format!("Token{}{}", COPY_NAME_DELIMITER, copy_id));
+            }
+            assert!(contract.nft_token(format!("{}{}{}", token_id, COPY_DELIMITER, copies + 1)).is_none());
+            assert_eq!(near_sdk::test_utils::get_created_receipts().len(), 1);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough gas to mint 61 tokens")]
+    fn mint_copies_checks_gas_upfront() {
+        let mut contract = setup_contract();
+        testing_env!(context(accounts(0), 10 * ONE_NEAR).build());
+        contract.nft_mint(accounts(1), token_metadata(Some(61)), None, None, None, None);
+    }
+
+    pub(crate) fn collection_metadata_js(custom_collection_id: Option<String>) -> CollectionMetadataJs {
+        CollectionMetadataJs {
+            title: "Collection".to_string(),
+            desc: "".to_string(),
+            media: "".to_string(),
+            reference: None,
+            custom_collection_id,
+            tags: None,
+            category: None,
+            transfer_policy: None,
+            locked_until: None,
+        }
+    }
+
+    #[test]
+    fn squatted_collection_id_is_skipped() {
+        let mut contract = setup_contract();
+        // custom ids weren't validated before the `collection-` prefix was reserved
+        let squatted_id = format!("{}{}{}", COLLECTION_TAG, DELIMITER, 1);
+        contract.collections.insert(&squatted_id, &CollectionMetadata {
+            collection_id: squatted_id.clone(),
+            collection_contract: MJOL_CONTRACT.to_string(),
+            owner_id: accounts(2),
+            title: "Squatter".to_string(),
+            desc: "".to_string(),
+            media: "".to_string(),
+            reference: None,
+        });
+
+        testing_env!(context(accounts(0), ONE_NEAR).build());
+        let collection = contract.create_collection(collection_metadata_js(None));
+        assert_eq!(collection.collection_id, format!("{}{}{}", COLLECTION_TAG, DELIMITER, 2));
+        assert_eq!(contract.collections.get(&squatted_id).unwrap().owner_id, accounts(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Id can't start with reserved prefix")]
+    fn reserved_collection_id_is_rejected() {
+        let mut contract = setup_contract();
+        testing_env!(context(accounts(0), ONE_NEAR).build());
+        contract.create_collection(collection_metadata_js(Some("collection-3".to_string())));
+    }
+
+    #[test]
+    #[should_panic(expected = "Royalties can't exceed")]
+    fn royalties_above_cap_are_rejected() {
+        let mut contract = setup_contract();
+        let mut royalties = HashMap::new();
+        royalties.insert(accounts(2), U128(MAX_PAYOUT - MAX_PLATFORM_FEE as u128 + 1));
+        testing_env!(context(accounts(0), ONE_NEAR).build());
+        contract.nft_mint(accounts(1), token_metadata(None), Some(Payout { payout: royalties }), None, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token id is already taken")]
+    fn burned_token_id_cant_be_minted_again() {
+        let mut contract = setup_contract();
+        testing_env!(context(accounts(0), ONE_NEAR).build());
+        contract.nft_mint(accounts(1), token_metadata(None), None, None, Some("burned".to_string()), None);
+        testing_env!(context(accounts(1), 1).build());
+        contract.nft_burn("burned".to_string());
+
+        testing_env!(context(accounts(0), ONE_NEAR).build());
+        contract.nft_mint(accounts(2), token_metadata(None), None, None, Some("burned".to_string()), None);
+    }
+
+    fn mint_with_min_price_approval(contract: &mut Contract, token_id: &str) {
+        testing_env!(context(accounts(0), ONE_NEAR).build());
+        contract.nft_mint(accounts(1), token_metadata(None), None, None, Some(token_id.to_string()), None);
+        testing_env!(context(accounts(1), ONE_NEAR).build());
+        contract.nft_approve_with_constraints(token_id.to_string(), accounts(2), None, None, Some(U128(ONE_NEAR)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Approval has a minimal price, use nft_transfer_payout")]
+    fn min_price_approval_cant_transfer_without_price() {
+        let mut contract = setup_contract();
+        mint_with_min_price_approval(&mut contract, "priced");
+        testing_env!(context(accounts(2), 1).build());
+        contract.nft_transfer(accounts(3), "priced".to_string(), Some(1), None);
+    }
+
+    #[test]
+    fn min_price_approval_sells_by_transfer_payout() {
+        let mut contract = setup_contract();
+        mint_with_min_price_approval(&mut contract, "priced");
+        testing_env!(context(accounts(2), 1).build());
+        let payout = contract.nft_transfer_payout(accounts(3), "priced".to_string(), 1, U128(ONE_NEAR), 10);
+        assert_eq!(payout_sum(&payout), ONE_NEAR);
+        assert_eq!(contract.nft_token("priced".to_string()).unwrap().owner_id, accounts(3));
+        assert!(contract.approval_constraints.get(&("priced".to_string(), accounts(2))).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "to cover storage")]
+    fn constrained_approval_storage_is_paid_by_owner() {
+        let mut contract = setup_contract();
+        testing_env!(context(accounts(0), ONE_NEAR).build());
+        contract.nft_mint(accounts(1), token_metadata(None), None, None, Some("approved".to_string()), None);
+        testing_env!(context(accounts(1), 1).build());
+        contract.nft_approve_with_constraints("approved".to_string(), accounts(2), None, None, Some(U128(ONE_NEAR)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Token is on auction with bids")]
+    fn token_with_bids_cant_be_transferred() {
+        let mut contract = setup_contract();
+        testing_env!(context(accounts(0), ONE_NEAR).build());
+        contract.nft_mint(accounts(1), token_metadata(None), None, None, Some("auctioned".to_string()), None);
+        testing_env!(context(accounts(1), ONE_NEAR).build());
+        contract.create_auction("auctioned".to_string(), U128(ONE_NEAR), U64(1_000_000_000_000));
+        testing_env!(context(accounts(2), ONE_NEAR).build());
+        contract.place_bid("auctioned".to_string());
+
+        testing_env!(context(accounts(1), 1).build());
+        contract.nft_transfer(accounts(3), "auctioned".to_string(), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Reserve price must be positive")]
+    fn auction_without_reserve_price_is_rejected() {
+        let mut contract = setup_contract();
+        testing_env!(context(accounts(0), ONE_NEAR).build());
+        contract.nft_mint(accounts(1), token_metadata(None), None, None, Some("auctioned".to_string()), None);
+        testing_env!(context(accounts(1), ONE_NEAR).build());
+        contract.create_auction("auctioned".to_string(), U128(0), U64(1_000_000_000_000));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner or issuer can burn the token")]
+    fn recreated_collection_doesnt_inherit_soulbound_policy() {
+        let mut contract = setup_contract();
+        let mut metadata = collection_metadata_js(Some("badges".to_string()));
+        metadata.transfer_policy = Some(TransferPolicy::Soulbound);
+        testing_env!(context(accounts(0), ONE_NEAR).build());
+        contract.create_collection(metadata);
+        contract.nft_mint(accounts(1), token_metadata(None), None, Some("badges".to_string()), None, None);
+        testing_env!(context(contract_id(), 0).build());
+        contract.remove_collection("badges".to_string(), accounts(0));
+
+        testing_env!(context(accounts(2), ONE_NEAR).build());
+        contract.create_collection(collection_metadata_js(Some("badges".to_string())));
+        assert!(contract.internal_collection_transfer_policy(&"badges".to_string()) == TransferPolicy::Transferable);
+        testing_env!(context(accounts(2), 1).build());
+        contract.nft_burn(format!("{}{}{}", TOKEN_TAG, DELIMITER, 1));
+    }
+
+    #[test]
+    fn recreated_collection_doesnt_inherit_lock() {
+        let mut contract = setup_contract();
+        let mut metadata = collection_metadata_js(Some("locked".to_string()));
+        metadata.locked_until = Some(U64(u64::MAX));
+        testing_env!(context(accounts(0), ONE_NEAR).build());
+        contract.create_collection(metadata);
+        testing_env!(context(contract_id(), 0).build());
+        contract.remove_collection("locked".to_string(), accounts(0));
+        assert!(contract.collection_locked_until.get(&"locked".to_string()).is_none());
+
+        testing_env!(context(accounts(2), ONE_NEAR).build());
+        contract.create_collection(collection_metadata_js(Some("locked".to_string())));
+        contract.nft_mint(accounts(1), token_metadata(None), None, Some("locked".to_string()), None, None);
+        testing_env!(context(accounts(1), 1).build());
+        contract.nft_transfer(accounts(3), format!("{}{}{}", TOKEN_TAG, DELIMITER, 1), None, None);
+    }
+
+    #[test]
+    fn collection_royalties_skip_burned_tokens() {
+        let mut contract = setup_contract();
+        testing_env!(context(accounts(0), ONE_NEAR).build());
+        let collection_id = contract.create_collection(collection_metadata_js(None)).collection_id;
+        for _ in 0..2 {
+            contract.nft_mint(accounts(1), token_metadata(None), None, Some(collection_id.clone()), None, None);
+        }
+        let burned_id = format!("{}{}{}", TOKEN_TAG, DELIMITER, 1);
+        // legacy tokens have no creator recorded
+        contract.token_creators.remove(&burned_id);
+        testing_env!(context(accounts(1), 1).build());
+        contract.nft_burn(burned_id.clone());
+
+        let mut royalties = HashMap::new();
+        royalties.insert(accounts(2), U128(1_000));
+        testing_env!(context(accounts(0), ONE_NEAR).build());
+        contract.update_collection_royalties(collection_id, Payout { payout: royalties }, 0, 10);
+        assert!(contract.payouts.get(&burned_id).is_none());
+        assert!(contract.payouts.get(&format!("{}{}{}", TOKEN_TAG, DELIMITER, 2)).is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "Royalties can have at most 9 receivers")]
+    fn royalties_leave_payout_slot_for_treasury() {
+        let mut contract = setup_contract();
+        let royalties = (0..MAX_LEN_PAYOUT)
+            .map(|i| (format!("royalty{}.near", i).parse().unwrap(), U128(1)))
+            .collect();
+        testing_env!(context(accounts(0), ONE_NEAR).build());
+        contract.nft_mint(accounts(1), token_metadata(None), Some(Payout { payout: royalties }), None, None, None);
+    }
+
+    #[test]
+    fn issued_at_is_in_milliseconds() {
+        let mut contract = setup_contract();
+        testing_env!(context(accounts(0), ONE_NEAR).block_timestamp(1_600_000_000_123_456_789).build());
+        contract.nft_mint(accounts(1), token_metadata(None), None, None, Some("dated".to_string()), None);
+        let token = contract.nft_token("dated".to_string()).unwrap();
+        assert_eq!(token.metadata.unwrap().issued_at, Some("1600000000123".to_string()));
+        assert_eq!(contract.token_minted_at.get(&"dated".to_string()), Some(1_600_000_000_123_456_789));
+    }
+}
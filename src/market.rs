@@ -0,0 +1,291 @@
+use crate::*;
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Listing {
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+    pub price: U128,
+    pub collection_id: Option<CollectionId>,
+    pub listed_at: U64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ListingInfo {
+    #[serde(flatten)]
+    pub listing: Listing,
+    /// Fungible token the price is set in, `None` for yoctoNEAR.
+    pub ft_token_id: Option<AccountId>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Lists own token for sale at `price` yoctoNEAR, or in whitelisted `ft_token_id`
+    /// if given. Storage of the listing is paid from the attached deposit.
+    #[payable]
+    pub fn list_token(&mut self, token_id: TokenId, price: U128, ft_token_id: Option<AccountId>) -> ListingInfo {
+        let owner_id = env::predecessor_account_id();
+        assert_eq!(self.tokens.owner_by_id.get(&token_id),
+                   Some(owner_id.clone()),
+                   "Only owner of token can list it");
+        assert!(price.0 > 0, "Price must be positive");
+        assert!(self.auctions.get(&token_id).is_none(), "Token is on auction");
+        if let Some(ft_token_id) = &ft_token_id {
+            self.assert_ft_token_whitelisted(ft_token_id);
+        }
+        self.assert_token_transferable(&token_id);
+
+        self.internal_remove_listing(&token_id);
+        let initial_storage_usage = env::storage_usage();
+        let listing = Listing {
+            token_id: token_id.clone(),
+            owner_id,
+            price,
+            collection_id: self.internal_token_collection_id(&token_id),
+            listed_at: U64(env::block_timestamp()),
+        };
+        self.listings.insert(&token_id, &listing);
+        if let Some(collection_id) = &listing.collection_id {
+            let mut token_ids = self
+                .listings_by_collection
+                .get(collection_id)
+                .unwrap_or_else(||
+                    UnorderedSet::new(StorageKey::ListingsByCollectionInner {
+                        collection_id_hash: hash_string(collection_id)
+                    }.try_to_vec().unwrap()));
+            token_ids.insert(&token_id);
+            self.listings_by_collection.insert(collection_id, &token_ids);
+        }
+        if let Some(ft_token_id) = &ft_token_id {
+            self.listing_ft_tokens.insert(&token_id, ft_token_id);
+        }
+        refund_storage_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+
+        let listing_info = ListingInfo { listing, ft_token_id };
+        env::log_str(&json!({
+            "type": "list_token",
+            "data": listing_info
+        }).to_string());
+
+        listing_info
+    }
+
+    #[payable]
+    pub fn delist_token(&mut self, token_id: TokenId) {
+        assert_one_yocto();
+        let listing = self.listings.get(&token_id).expect("Error: token is not listed.");
+        assert_eq!(listing.owner_id, env::predecessor_account_id(), "Only owner of token can delist it");
+
+        self.internal_remove_listing(&token_id);
+
+        env::log_str(&json!({
+            "type": "delist_token",
+            "data": {
+                "token_id": token_id,
+                "owner_id": listing.owner_id
+            }
+        }).to_string());
+    }
+
+    /// Buys the listed token for the attached deposit. Royalties, platform fee and
+    /// the owner are paid according to `nft_payout`, excess deposit is refunded.
+    /// Tokens listed in fungible tokens are bought with `ft_transfer_call`.
+    #[payable]
+    pub fn buy_token(&mut self, token_id: TokenId) -> Payout {
+        let buyer_id = env::predecessor_account_id();
+        let deposit = env::attached_deposit();
+        let (payout, price) = self.internal_buy_token(&token_id, &buyer_id, deposit, None);
+        if deposit > price {
+            Promise::new(buyer_id).transfer(deposit - price);
+        }
+        payout
+    }
+
+    pub fn get_listing(&self, token_id: TokenId) -> Option<ListingInfo> {
+        self.listings.get(&token_id).map(|listing| self.internal_listing_info(listing))
+    }
+
+    pub fn get_listings(&self, from: u64, limit: u64) -> Vec<ListingInfo> {
+        let to = std::cmp::min(from.saturating_add(limit), self.listings.len());
+        (from..to)
+            .filter_map(|i| self.listings.values_as_vector().get(i))
+            .map(|listing| self.internal_listing_info(listing))
+            .collect()
+    }
+
+    pub fn get_listings_by_collection(&self, collection_id: CollectionId, from: u64, limit: u64) -> Vec<ListingInfo> {
+        self
+            .listings_by_collection
+            .get(&collection_id)
+            .map(|token_ids| token_ids
+                .iter()
+                .skip(from as usize)
+                .take(limit as usize)
+                .filter_map(|token_id| self.listings.get(&token_id))
+                .map(|listing| self.internal_listing_info(listing))
+                .collect())
+            .unwrap_or_default()
+    }
+
+    /// Sells the listed token for `amount` of yoctoNEAR or of `ft_token_id`, which has
+    /// to match the listing currency. Returns the payout and the price actually charged.
+    pub(crate) fn internal_buy_token(&mut self,
+                                     token_id: &TokenId,
+                                     buyer_id: &AccountId,
+                                     amount: u128,
+                                     ft_token_id: Option<&AccountId>) -> (Payout, u128) {
+        let listing = self.listings.get(token_id).expect("Error: token is not listed.");
+        let listing_ft_token_id = self.listing_ft_tokens.get(token_id);
+        assert_eq!(listing_ft_token_id.as_ref(), ft_token_id, "Token is listed in another currency");
+        assert!(amount >= listing.price.0, "Attached amount is less than price {}", listing.price.0);
+
+        let payout = self.internal_sell_token(token_id, &listing.owner_id, buyer_id, listing.price, ft_token_id);
+
+        env::log_str(&json!({
+            "type": "buy_token",
+            "data": {
+                "token_id": token_id,
+                "seller_id": listing.owner_id,
+                "buyer_id": buyer_id,
+                "price": listing.price,
+                "ft_token_id": listing_ft_token_id,
+                "collection_id": listing.collection_id
+            }
+        }).to_string());
+
+        (payout, listing.price.0)
+    }
+
+    /// Moves the token from `seller_id` to `buyer_id` and pays `price` out between
+    /// royalty receivers, treasury and the seller, in `ft_token_id` if given.
+    pub(crate) fn internal_sell_token(&mut self,
+                                      token_id: &TokenId,
+                                      seller_id: &AccountId,
+                                      buyer_id: &AccountId,
+                                      price: U128,
+                                      ft_token_id: Option<&AccountId>) -> Payout {
+        assert_eq!(self.tokens.owner_by_id.get(token_id).as_ref(),
+                   Some(seller_id),
+                   "Seller doesn't own the token anymore");
+        assert_ne!(seller_id, buyer_id, "Seller can't buy own token");
+        self.assert_token_transferable(token_id);
+        self.assert_token_unlocked(token_id);
+
+        let payout = self.nft_payout(token_id.clone(), price, MAX_LEN_PAYOUT as u32 + 1);
+        let approved_ids = self.internal_token_approvals(token_id).into_keys().collect();
+        self.internal_refund_approval_constraints(token_id, seller_id, approved_ids);
+        self.tokens.internal_transfer(seller_id, buyer_id, token_id, None, None);
+        self.internal_on_token_moved(token_id, seller_id, buyer_id, true);
+        self.internal_remove_listing(token_id);
+
+        match ft_token_id {
+            Some(ft_token_id) => self.internal_ft_payout(ft_token_id, &payout),
+            None => for (receiver_id, amount) in payout.payout.iter() {
+                if amount.0 > 0 {
+                    Promise::new(receiver_id.clone()).transfer(amount.0);
+                }
+            }
+        }
+        payout
+    }
+
+    fn internal_listing_info(&self, listing: Listing) -> ListingInfo {
+        let ft_token_id = self.listing_ft_tokens.get(&listing.token_id);
+        ListingInfo { listing, ft_token_id }
+    }
+
+    /// Drops the listing of the token and refunds its storage to the account which
+    /// listed it. Returns the released storage in bytes.
+    pub(crate) fn internal_remove_listing(&mut self, token_id: &TokenId) -> u64 {
+        let initial_storage_usage = env::storage_usage();
+        self.listing_ft_tokens.remove(token_id);
+        let listing = match self.listings.remove(token_id) {
+            Some(listing) => listing,
+            None => return 0,
+        };
+        if let Some(collection_id) = &listing.collection_id {
+            if let Some(mut token_ids) = self.listings_by_collection.get(collection_id) {
+                token_ids.remove(token_id);
+                self.listings_by_collection.insert(collection_id, &token_ids);
+            }
+        }
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        if storage_released > 0 {
+            Promise::new(listing.owner_id).transfer(env::storage_byte_cost() * storage_released as u128);
+        }
+        storage_released
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, ONE_NEAR};
+
+    use crate::tests::{context, contract_id, setup_contract, token_metadata, transfers};
+    use super::*;
+
+    /// Token `listed` of `accounts(1)` with 10% royalty to `accounts(2)`, listed for `price`.
+    fn setup_listing(price: u128) -> Contract {
+        let mut contract = setup_contract();
+        testing_env!(context(contract_id(), 0).build());
+        contract.set_fee_config(accounts(5), 250);
+        let mut royalties = HashMap::new();
+        royalties.insert(accounts(2), U128(1_000));
+        testing_env!(context(accounts(0), ONE_NEAR).build());
+        contract.nft_mint(accounts(1),
+                          token_metadata(None),
+                          Some(Payout { payout: royalties }),
+                          None,
+                          Some("listed".to_string()),
+                          None);
+        testing_env!(context(accounts(1), ONE_NEAR).build());
+        contract.list_token("listed".to_string(), U128(price), None);
+        contract
+    }
+
+    #[test]
+    fn buy_token_pays_out_and_refunds_overpayment() {
+        let mut contract = setup_listing(10 * ONE_NEAR);
+        testing_env!(context(accounts(3), 12 * ONE_NEAR).build());
+        let payout = contract.buy_token("listed".to_string());
+
+        assert_eq!(payout.payout.values().map(|amount| amount.0).sum::<u128>(), 10 * ONE_NEAR);
+        let transfers = transfers();
+        assert!(transfers.contains(&(accounts(2), ONE_NEAR)));
+        assert!(transfers.contains(&(accounts(5), ONE_NEAR / 4)));
+        assert!(transfers.contains(&(accounts(1), 10 * ONE_NEAR - ONE_NEAR - ONE_NEAR / 4)));
+        assert!(transfers.contains(&(accounts(3), 2 * ONE_NEAR)));
+        // storage of the listing goes back to the seller
+        assert_eq!(transfers.iter().filter(|(account_id, _)| account_id == &accounts(1)).count(), 2);
+        assert_eq!(contract.nft_token("listed".to_string()).unwrap().owner_id, accounts(3));
+        assert!(contract.get_listing("listed".to_string()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Attached amount is less than price")]
+    fn buy_token_rejects_lower_deposit() {
+        let mut contract = setup_listing(10 * ONE_NEAR);
+        testing_env!(context(accounts(3), ONE_NEAR).build());
+        contract.buy_token("listed".to_string());
+    }
+
+    #[test]
+    fn transfer_refunds_listing_storage() {
+        let mut contract = setup_listing(10 * ONE_NEAR);
+        testing_env!(context(accounts(1), 1).build());
+        contract.nft_transfer(accounts(3), "listed".to_string(), None, None);
+
+        assert!(contract.get_listing("listed".to_string()).is_none());
+        let refunds: Vec<u128> = transfers()
+            .into_iter()
+            .filter(|(account_id, _)| account_id == &accounts(1))
+            .map(|(_, amount)| amount)
+            .collect();
+        assert_eq!(refunds.len(), 1);
+        assert!(refunds[0] > 0);
+    }
+}
@@ -0,0 +1,116 @@
+use crate::*;
+use near_sdk::json_types::U64;
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum CollectionStatus {
+    Verified,
+    /// Left out of collection lists and search unless asked for by status.
+    Hidden,
+    /// Still listed, frontends are expected to warn about it using `get_collection_moderation`.
+    Flagged,
+}
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CollectionModeration {
+    pub status: CollectionStatus,
+    pub reason: Option<String>,
+    pub moderator_id: AccountId,
+    pub updated_at: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[private]
+    pub fn add_moderator(&mut self, account_id: AccountId) {
+        self.moderators.insert(&account_id);
+
+        env::log_str(&json!({
+            "type": "add_moderator",
+            "data": {
+                "account_id": account_id
+            }
+        }).to_string())
+    }
+
+    #[private]
+    pub fn remove_moderator(&mut self, account_id: AccountId) {
+        self.moderators.remove(&account_id);
+
+        env::log_str(&json!({
+            "type": "remove_moderator",
+            "data": {
+                "account_id": account_id
+            }
+        }).to_string())
+    }
+
+    pub fn get_moderators(&self) -> Vec<AccountId> {
+        self.moderators.to_vec()
+    }
+
+    /// Sets moderation status of the collection, `None` resets it to unreviewed.
+    #[payable]
+    pub fn set_collection_status(&mut self,
+                                 collection_id: CollectionId,
+                                 status: Option<CollectionStatus>,
+                                 reason: Option<String>) {
+        assert_one_yocto();
+        let moderator_id = env::predecessor_account_id();
+        assert!(self.moderators.contains(&moderator_id) || moderator_id == env::current_account_id(),
+                "Only moderators can change collection status");
+        assert!(self.collections.get(&collection_id).is_some(), "Error: no such collection id.");
+
+        self.internal_clear_collection_status(&collection_id);
+        if let Some(status) = status {
+            let mut collection_ids = self
+                .collections_by_status
+                .get(&status)
+                .unwrap_or_else(||
+                    UnorderedSet::new(StorageKey::CollectionsByStatusInner { status }
+                        .try_to_vec().unwrap()));
+            collection_ids.insert(&collection_id);
+            self.collections_by_status.insert(&status, &collection_ids);
+            self.collection_moderation.insert(&collection_id, &CollectionModeration {
+                status,
+                reason: reason.clone(),
+                moderator_id: moderator_id.clone(),
+                updated_at: U64(env::block_timestamp()),
+            });
+        }
+
+        env::log_str(&json!({
+            "type": "set_collection_status",
+            "data": {
+                "collection_id": collection_id,
+                "status": status,
+                "reason": reason,
+                "moderator_id": moderator_id,
+                "updated_at": U64(env::block_timestamp())
+            }
+        }).to_string());
+    }
+
+    pub fn get_collection_moderation(&self, collection_id: CollectionId) -> Option<CollectionModeration> {
+        self.collection_moderation.get(&collection_id)
+    }
+
+    pub(crate) fn internal_is_collection_hidden(&self, collection_id: &CollectionId) -> bool {
+        self
+            .collection_moderation
+            .get(collection_id)
+            .map(|moderation| moderation.status == CollectionStatus::Hidden)
+            .unwrap_or(false)
+    }
+
+    pub(crate) fn internal_clear_collection_status(&mut self, collection_id: &CollectionId) {
+        if let Some(moderation) = self.collection_moderation.remove(collection_id) {
+            if let Some(mut collection_ids) = self.collections_by_status.get(&moderation.status) {
+                collection_ids.remove(collection_id);
+                self.collections_by_status.insert(&moderation.status, &collection_ids);
+            }
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use crate::*;
+
+pub type OfferId = u64;
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Offer {
+    pub offer_id: U64,
+    pub buyer_id: AccountId,
+    /// Offer on a single token, `None` for an offer on any token of `collection_id`.
+    pub token_id: Option<TokenId>,
+    pub collection_id: Option<CollectionId>,
+    pub amount: U128,
+    pub expires_at: Option<U64>,
+    pub created_at: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Escrows the attached deposit as an offer on `token_id` or on any token of
+    /// `collection_id`. Storage of the offer is taken from the deposit.
+    #[payable]
+    pub fn make_offer(&mut self,
+                      token_id: Option<TokenId>,
+                      collection_id: Option<CollectionId>,
+                      expires_at: Option<U64>) -> Offer {
+        let buyer_id = env::predecessor_account_id();
+        let now = env::block_timestamp();
+        if let Some(expires_at) = expires_at {
+            assert!(expires_at.0 > now, "Offer must expire in the future");
+        }
+        let collection_id = match (&token_id, collection_id) {
+            (Some(token_id), None) => {
+                let owner_id = self.tokens.owner_by_id.get(token_id).expect("Error: no such token id.");
+                assert_ne!(owner_id, buyer_id, "Can't make offer on own token");
+                self.internal_token_collection_id(token_id)
+            }
+            (None, Some(collection_id)) => {
+                assert!(self.collections.get(&collection_id).is_some(), "Error: no such collection id.");
+                Some(collection_id)
+            }
+            _ => env::panic_str("Offer must be made either on token or on collection"),
+        };
+
+        let initial_storage_usage = env::storage_usage();
+        let offer_id = self.next_offer_id;
+        self.next_offer_id += 1;
+        let mut offer = Offer {
+            offer_id: U64(offer_id),
+            buyer_id,
+            token_id,
+            collection_id,
+            amount: U128(0),
+            expires_at,
+            created_at: U64(now),
+        };
+        self.offers.insert(&offer_id, &offer);
+        match &offer.token_id {
+            Some(token_id) => {
+                let mut offer_ids = self
+                    .offers_by_token
+                    .get(token_id)
+                    .unwrap_or_else(||
+                        UnorderedSet::new(StorageKey::OffersByTokenInner {
+                            token_id_hash: hash_string(token_id)
+                        }.try_to_vec().unwrap()));
+                offer_ids.insert(&offer_id);
+                self.offers_by_token.insert(token_id, &offer_ids);
+            }
+            None => {
+                let collection_id = offer.collection_id.as_ref().unwrap();
+                let mut offer_ids = self
+                    .offers_by_collection
+                    .get(collection_id)
+                    .unwrap_or_else(||
+                        UnorderedSet::new(StorageKey::OffersByCollectionInner {
+                            collection_id_hash: hash_string(collection_id)
+                        }.try_to_vec().unwrap()));
+                offer_ids.insert(&offer_id);
+                self.offers_by_collection.insert(collection_id, &offer_ids);
+            }
+        }
+
+        let storage_cost = env::storage_byte_cost()
+            * env::storage_usage().saturating_sub(initial_storage_usage) as u128;
+        let deposit = env::attached_deposit();
+        assert!(deposit > storage_cost,
+                "Must attach more than {} yoctoNEAR to cover storage", storage_cost);
+        offer.amount = U128(deposit - storage_cost);
+        self.offers.insert(&offer_id, &offer);
+
+        env::log_str(&json!({
+            "type": "make_offer",
+            "data": offer.clone()
+        }).to_string());
+
+        offer
+    }
+
+    /// Returns escrowed amount and storage deposit of the offer to its buyer.
+    #[payable]
+    pub fn withdraw_offer(&mut self, offer_id: U64) {
+        assert_one_yocto();
+        let offer = self.offers.get(&offer_id.0).expect("Error: no such offer id.");
+        assert_eq!(offer.buyer_id, env::predecessor_account_id(), "Only buyer can withdraw offer");
+
+        self.internal_remove_offer(&offer, true);
+
+        env::log_str(&json!({
+            "type": "withdraw_offer",
+            "data": {
+                "offer_id": offer_id,
+                "buyer_id": offer.buyer_id
+            }
+        }).to_string());
+    }
+
+    /// Sells own token to the buyer of the offer. Offers on a collection need the
+    /// `token_id` of the collection to sell. The amount is paid out according to `nft_payout`.
+    #[payable]
+    pub fn accept_offer(&mut self, offer_id: U64, token_id: Option<TokenId>) -> Payout {
+        assert_one_yocto();
+        let offer = self.offers.get(&offer_id.0).expect("Error: no such offer id.");
+        if let Some(expires_at) = offer.expires_at {
+            assert!(env::block_timestamp() < expires_at.0, "Offer has expired");
+        }
+        let token_id = match (&offer.token_id, token_id) {
+            (Some(offer_token_id), None) => offer_token_id.clone(),
+            (Some(offer_token_id), Some(token_id)) => {
+                assert_eq!(offer_token_id, &token_id, "Offer was made on another token");
+                token_id
+            }
+            (None, Some(token_id)) => {
+                assert_eq!(self.internal_token_collection_id(&token_id),
+                           offer.collection_id,
+                           "Token doesn't belong to the offer collection");
+                token_id
+            }
+            (None, None) => env::panic_str("Token id is required to accept collection offer"),
+        };
+        let seller_id = env::predecessor_account_id();
+        assert!(self.auctions.get(&token_id).is_none(), "Token is on auction");
+
+        let payout = self.internal_sell_token(&token_id, &seller_id, &offer.buyer_id, offer.amount, None);
+        self.internal_remove_offer(&offer, false);
+
+        env::log_str(&json!({
+            "type": "accept_offer",
+            "data": {
+                "offer_id": offer_id,
+                "token_id": token_id,
+                "seller_id": seller_id,
+                "buyer_id": offer.buyer_id,
+                "price": offer.amount,
+                "collection_id": offer.collection_id
+            }
+        }).to_string());
+
+        payout
+    }
+
+    pub fn get_offer(&self, offer_id: U64) -> Option<Offer> {
+        self.offers.get(&offer_id.0)
+    }
+
+    pub fn get_offers_for_token(&self, token_id: TokenId, from: u64, limit: u64) -> Vec<Offer> {
+        self.internal_offers_page(self.offers_by_token.get(&token_id), from, limit)
+    }
+
+    /// Offers on any token of the collection, offers on single tokens aren't included.
+    pub fn get_offers_for_collection(&self, collection_id: CollectionId, from: u64, limit: u64) -> Vec<Offer> {
+        self.internal_offers_page(self.offers_by_collection.get(&collection_id), from, limit)
+    }
+
+    fn internal_offers_page(&self, offer_ids: Option<UnorderedSet<OfferId>>, from: u64, limit: u64) -> Vec<Offer> {
+        offer_ids
+            .map(|offer_ids| offer_ids
+                .iter()
+                .skip(from as usize)
+                .take(limit as usize)
+                .filter_map(|offer_id| self.offers.get(&offer_id))
+                .collect())
+            .unwrap_or_default()
+    }
+
+    /// Removes the offer and refunds released storage to its buyer, together with
+    /// the escrowed amount when `refund_amount` is set.
+    fn internal_remove_offer(&mut self, offer: &Offer, refund_amount: bool) {
+        let initial_storage_usage = env::storage_usage();
+        let offer_id = offer.offer_id.0;
+        self.offers.remove(&offer_id);
+        if let Some(token_id) = &offer.token_id {
+            if let Some(mut offer_ids) = self.offers_by_token.get(token_id) {
+                offer_ids.remove(&offer_id);
+                self.offers_by_token.insert(token_id, &offer_ids);
+            }
+        } else if let Some(collection_id) = &offer.collection_id {
+            if let Some(mut offer_ids) = self.offers_by_collection.get(collection_id) {
+                offer_ids.remove(&offer_id);
+                self.offers_by_collection.insert(collection_id, &offer_ids);
+            }
+        }
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        let mut refund = env::storage_byte_cost() * storage_released as u128;
+        if refund_amount {
+            refund += offer.amount.0;
+        }
+        if refund > 0 {
+            Promise::new(offer.buyer_id.clone()).transfer(refund);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, ONE_NEAR};
+
+    use crate::tests::{collection_metadata_js, context, setup_contract, token_metadata, transfers};
+    use super::*;
+
+    const EXPIRES_AT: u64 = 1_000_000;
+
+    /// Tokens `token-1` of collection `aaa` and `token-2` of `bbb`, both owned by `accounts(1)`.
+    fn setup_tokens() -> Contract {
+        let mut contract = setup_contract();
+        testing_env!(context(accounts(0), ONE_NEAR).build());
+        for collection_id in ["aaa", "bbb"] {
+            contract.create_collection(collection_metadata_js(Some(collection_id.to_string())));
+            contract.nft_mint(accounts(1), token_metadata(None), None, Some(collection_id.to_string()), None, None);
+        }
+        contract
+    }
+
+    fn token_id(index: u64) -> TokenId {
+        format!("{}{}{}", TOKEN_TAG, DELIMITER, index)
+    }
+
+    #[test]
+    fn make_offer_takes_storage_from_deposit() {
+        let mut contract = setup_tokens();
+        testing_env!(context(accounts(2), ONE_NEAR).build());
+        let initial_storage_usage = env::storage_usage();
+        let offer = contract.make_offer(Some(token_id(1)), None, None);
+        let storage_cost = env::storage_byte_cost() * (env::storage_usage() - initial_storage_usage) as u128;
+
+        assert!(storage_cost > 0);
+        assert_eq!(offer.amount.0, ONE_NEAR - storage_cost);
+        assert_eq!(offer.collection_id, Some("aaa".to_string()));
+        assert_eq!(contract.get_offers_for_token(token_id(1), 0, 10).len(), 1);
+    }
+
+    #[test]
+    fn withdraw_offer_refunds_amount_and_storage() {
+        let mut contract = setup_tokens();
+        testing_env!(context(accounts(2), ONE_NEAR).build());
+        let offer = contract.make_offer(Some(token_id(1)), None, None);
+
+        testing_env!(context(accounts(2), 1).build());
+        contract.withdraw_offer(offer.offer_id);
+        let refunds: Vec<u128> = transfers()
+            .into_iter()
+            .filter(|(account_id, _)| account_id == &accounts(2))
+            .map(|(_, amount)| amount)
+            .collect();
+        assert_eq!(refunds.len(), 1);
+        assert!(refunds[0] > offer.amount.0 && refunds[0] <= ONE_NEAR);
+        assert!(contract.get_offer(offer.offer_id).is_none());
+        assert!(contract.get_offers_for_token(token_id(1), 0, 10).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Offer has expired")]
+    fn expired_offer_cant_be_accepted() {
+        let mut contract = setup_tokens();
+        testing_env!(context(accounts(2), ONE_NEAR).build());
+        let offer = contract.make_offer(Some(token_id(1)), None, Some(U64(EXPIRES_AT)));
+
+        testing_env!(context(accounts(1), 1).block_timestamp(EXPIRES_AT).build());
+        contract.accept_offer(offer.offer_id, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token doesn't belong to the offer collection")]
+    fn collection_offer_rejects_token_of_other_collection() {
+        let mut contract = setup_tokens();
+        testing_env!(context(accounts(2), ONE_NEAR).build());
+        let offer = contract.make_offer(None, Some("aaa".to_string()), None);
+
+        testing_env!(context(accounts(1), 1).build());
+        contract.accept_offer(offer.offer_id, Some(token_id(2)));
+    }
+
+    #[test]
+    fn accepted_collection_offer_pays_out_escrow() {
+        let mut contract = setup_tokens();
+        testing_env!(context(accounts(2), ONE_NEAR).build());
+        let offer = contract.make_offer(None, Some("aaa".to_string()), None);
+
+        testing_env!(context(accounts(1), 1).build());
+        let payout = contract.accept_offer(offer.offer_id, Some(token_id(1)));
+        assert_eq!(payout.payout.values().map(|amount| amount.0).sum::<u128>(), offer.amount.0);
+        assert!(transfers().contains(&(accounts(1), offer.amount.0)));
+        assert_eq!(contract.nft_token(token_id(1)).unwrap().owner_id, accounts(2));
+        assert!(contract.get_offer(offer.offer_id).is_none());
+    }
+}
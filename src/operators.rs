@@ -0,0 +1,215 @@
+use crate::*;
+use near_sdk::Gas;
+
+const GAS_FOR_RESOLVE_TRANSFER: Gas = Gas(5_000_000_000_000);
+const GAS_FOR_NFT_TRANSFER_CALL: Gas = Gas(30_000_000_000_000);
+
+/// Operator can transfer every token of the owner, or only tokens of `collection_id` if it's set.
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OperatorApproval {
+    pub operator_id: AccountId,
+    pub collection_id: Option<CollectionId>,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn nft_approve_operator(&mut self, operator_id: AccountId, collection_id: Option<CollectionId>) {
+        let owner_id = env::predecessor_account_id();
+        assert_ne!(owner_id, operator_id, "Owner can't be an operator of own tokens");
+        if let Some(collection_id) = &collection_id {
+            assert!(self.collections.get(collection_id).is_some(), "Error: no such collection id.");
+        }
+
+        let initial_storage_usage = env::storage_usage();
+        let approval = OperatorApproval { operator_id, collection_id };
+        let mut approvals = self
+            .operator_approvals
+            .get(&owner_id)
+            .unwrap_or_else(||
+                UnorderedSet::new(StorageKey::OperatorApprovalsInner {
+                    account_id_hash: hash_account_id(&owner_id)
+                }.try_to_vec().unwrap()));
+        approvals.insert(&approval);
+        self.operator_approvals.insert(&owner_id, &approvals);
+        refund_storage_deposit(env::storage_usage() - initial_storage_usage);
+
+        env::log_str(&json!({
+            "type": "nft_approve_operator",
+            "data": {
+                "owner_id": owner_id,
+                "operator_id": approval.operator_id,
+                "collection_id": approval.collection_id
+            }
+        }).to_string());
+    }
+
+    #[payable]
+    pub fn nft_revoke_operator(&mut self, operator_id: AccountId, collection_id: Option<CollectionId>) {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+        let approval = OperatorApproval { operator_id, collection_id };
+        if let Some(mut approvals) = self.operator_approvals.get(&owner_id) {
+            assert!(approvals.remove(&approval), "Operator is not approved");
+            if approvals.is_empty() {
+                self.operator_approvals.remove(&owner_id);
+            } else {
+                self.operator_approvals.insert(&owner_id, &approvals);
+            }
+        } else {
+            env::panic_str("Operator is not approved");
+        }
+        let storage_released = initial_storage_usage.saturating_sub(env::storage_usage());
+        if storage_released > 0 {
+            Promise::new(owner_id.clone()).transfer(env::storage_byte_cost() * storage_released as u128);
+        }
+
+        env::log_str(&json!({
+            "type": "nft_revoke_operator",
+            "data": {
+                "owner_id": owner_id,
+                "operator_id": approval.operator_id,
+                "collection_id": approval.collection_id
+            }
+        }).to_string());
+    }
+
+    pub fn nft_operators_for_owner(&self, account_id: AccountId) -> Vec<OperatorApproval> {
+        self
+            .operator_approvals
+            .get(&account_id)
+            .map(|approvals| approvals.to_vec())
+            .unwrap_or_default()
+    }
+
+    pub fn nft_is_operator_approved(&self, token_id: TokenId, operator_id: AccountId) -> bool {
+        self
+            .tokens.owner_by_id
+            .get(&token_id)
+            .map(|owner_id| self.internal_is_operator(&owner_id, &operator_id, &token_id))
+            .unwrap_or(false)
+    }
+
+    pub(crate) fn internal_is_operator(&self,
+                                       owner_id: &AccountId,
+                                       operator_id: &AccountId,
+                                       token_id: &TokenId) -> bool {
+        let approvals = match self.operator_approvals.get(owner_id) {
+            Some(approvals) => approvals,
+            None => return false,
+        };
+        let for_all_tokens = OperatorApproval {
+            operator_id: operator_id.clone(),
+            collection_id: None,
+        };
+        approvals.contains(&for_all_tokens) || self
+            .internal_token_collection_id(token_id)
+            .map(|collection_id| approvals.contains(&OperatorApproval {
+                operator_id: operator_id.clone(),
+                collection_id: Some(collection_id),
+            }))
+            .unwrap_or(false)
+    }
+
+    /// `nft_transfer_call` on behalf of the owner, mirrors the standard implementation.
+    pub(crate) fn internal_operator_transfer_call(&mut self,
+                                                  owner_id: AccountId,
+                                                  receiver_id: AccountId,
+                                                  token_id: TokenId,
+                                                  memo: Option<String>,
+                                                  msg: String) -> PromiseOrValue<bool> {
+        assert_one_yocto();
+        assert!(env::prepaid_gas() > GAS_FOR_NFT_TRANSFER_CALL, "More gas is required");
+        let (old_owner_id, old_approvals) = self
+            .tokens
+            .internal_transfer(&owner_id, &receiver_id, &token_id, None, memo);
+        Promise::new(receiver_id.clone())
+            .function_call(
+                "nft_on_transfer".to_string(),
+                json!({
+                    "sender_id": env::predecessor_account_id(),
+                    "previous_owner_id": old_owner_id,
+                    "token_id": token_id,
+                    "msg": msg
+                }).to_string().into_bytes(),
+                0,
+                env::prepaid_gas() - GAS_FOR_NFT_TRANSFER_CALL)
+            .then(Promise::new(env::current_account_id())
+                .function_call(
+                    "nft_resolve_transfer".to_string(),
+                    json!({
+                        "previous_owner_id": old_owner_id,
+                        "receiver_id": receiver_id,
+                        "token_id": token_id,
+                        "approved_account_ids": old_approvals
+                    }).to_string().into_bytes(),
+                    0,
+                    GAS_FOR_RESOLVE_TRANSFER))
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_contract_standards::non_fungible_token::approval::NonFungibleTokenApproval;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, ONE_NEAR};
+
+    use crate::tests::{collection_metadata_js, context, setup_contract, token_metadata};
+    use super::*;
+
+    /// `token-1` of collection `aaa` and `token-2` of `bbb` owned by `accounts(1)`,
+    /// who approves `accounts(2)` as an operator of `aaa`.
+    fn setup_collection_operator() -> Contract {
+        let mut contract = setup_contract();
+        testing_env!(context(accounts(0), ONE_NEAR).build());
+        for collection_id in ["aaa", "bbb"] {
+            contract.create_collection(collection_metadata_js(Some(collection_id.to_string())));
+            contract.nft_mint(accounts(1), token_metadata(None), None, Some(collection_id.to_string()), None, None);
+        }
+        testing_env!(context(accounts(1), ONE_NEAR).build());
+        contract.nft_approve_operator(accounts(2), Some("aaa".to_string()));
+        contract
+    }
+
+    fn token_id(index: u64) -> TokenId {
+        format!("{}{}{}", TOKEN_TAG, DELIMITER, index)
+    }
+
+    #[test]
+    fn collection_operator_moves_only_tokens_of_collection() {
+        let mut contract = setup_collection_operator();
+        assert!(contract.nft_is_operator_approved(token_id(1), accounts(2)));
+        // without operator rights the transfer falls back to the standard approval check
+        assert!(!contract.nft_is_operator_approved(token_id(2), accounts(2)));
+        assert!(!contract.nft_is_approved(token_id(2), accounts(2), None));
+
+        testing_env!(context(accounts(2), 1).build());
+        contract.nft_transfer(accounts(3), token_id(1), None, None);
+        assert_eq!(contract.nft_token(token_id(1)).unwrap().owner_id, accounts(3));
+        assert_eq!(contract.nft_token(token_id(2)).unwrap().owner_id, accounts(1));
+        // rights stay with the owner, not with the moved token
+        assert!(!contract.nft_is_operator_approved(token_id(1), accounts(2)));
+    }
+
+    #[test]
+    fn revoked_operator_loses_rights() {
+        let mut contract = setup_collection_operator();
+        testing_env!(context(accounts(1), 1).build());
+        contract.nft_revoke_operator(accounts(2), Some("aaa".to_string()));
+
+        assert!(contract.nft_operators_for_owner(accounts(1)).is_empty());
+        assert!(!contract.nft_is_operator_approved(token_id(1), accounts(2)));
+        assert!(!contract.nft_is_approved(token_id(1), accounts(2), None));
+    }
+
+    #[test]
+    #[should_panic(expected = "Operator is not approved")]
+    fn operator_of_other_collection_cant_be_revoked() {
+        let mut contract = setup_collection_operator();
+        testing_env!(context(accounts(1), 1).build());
+        contract.nft_revoke_operator(accounts(2), Some("bbb".to_string()));
+    }
+}
@@ -0,0 +1,116 @@
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    pub fn nft_tokens_for_owner_in_collection(&self,
+                                              account_id: AccountId,
+                                              collection_id: CollectionId,
+                                              from: u64,
+                                              limit: u64) -> Vec<Token> {
+        self
+            .tokens_by_owner_in_collection
+            .get(&(account_id, collection_id))
+            .map(|token_ids| token_ids
+                .iter()
+                .skip(from as usize)
+                .take(limit as usize)
+                .filter_map(|token_id| self.tokens.nft_token(token_id))
+                .collect())
+            .unwrap_or_default()
+    }
+
+    pub fn nft_supply_for_owner_in_collection(&self,
+                                              account_id: AccountId,
+                                              collection_id: CollectionId) -> u64 {
+        self
+            .tokens_by_owner_in_collection
+            .get(&(account_id, collection_id))
+            .map(|token_ids| token_ids.len())
+            .unwrap_or(0)
+    }
+
+    /// Fills owner index for collection tokens minted before it existed. Runs only
+    /// after `rebuild_collection_tokens` returned `None`, old vectors mix collections.
+    #[private]
+    pub fn index_tokens_by_owner_in_collection(&mut self,
+                                               collection_id: CollectionId,
+                                               from: u64,
+                                               limit: u64) -> u64 {
+        self.assert_collection_tokens_not_rebuilding();
+        let token_ids = self
+            .tokens_by_collection_id
+            .get(&collection_id)
+            .expect("Error: collection has no tokens.");
+        let to = std::cmp::min(from.saturating_add(limit), token_ids.len());
+        for i in from..to {
+            let token_id = token_ids.get(i).unwrap();
+            if let Some(owner_id) = self.tokens.owner_by_id.get(&token_id) {
+                self.internal_add_token_to_owner_in_collection(&owner_id, &collection_id, &token_id);
+            }
+        }
+        // tokens minted before stats existed are counted once the whole collection is indexed,
+        // burned ids are still in the vector
+        if to == token_ids.len() {
+            self.internal_update_collection_stats(&collection_id, |stats|
+                stats.minted = max(stats.minted, token_ids.len()));
+        }
+        to
+    }
+
+    pub(crate) fn internal_add_token_to_owner_in_collection(&mut self,
+                                                            owner_id: &AccountId,
+                                                            collection_id: &CollectionId,
+                                                            token_id: &TokenId) {
+        let key = (owner_id.clone(), collection_id.clone());
+        let mut token_ids = self
+            .tokens_by_owner_in_collection
+            .get(&key)
+            .unwrap_or_else(||
+                UnorderedSet::new(StorageKey::TokensByOwnerInCollectionInner {
+                    // both parts may contain the delimiter, so the pair is hashed as a whole
+                    owner_collection_hash: hash_bytes(&key.try_to_vec().unwrap())
+                }.try_to_vec().unwrap()));
+        if token_ids.is_empty() {
+            self.internal_update_collection_stats(collection_id, |stats| stats.holders += 1);
+        }
+        token_ids.insert(token_id);
+        self.tokens_by_owner_in_collection.insert(&key, &token_ids);
+    }
+
+    pub(crate) fn internal_remove_token_from_owner_in_collection(&mut self,
+                                                                 owner_id: &AccountId,
+                                                                 collection_id: &CollectionId,
+                                                                 token_id: &TokenId) {
+        let key = (owner_id.clone(), collection_id.clone());
+        if let Some(mut token_ids) = self.tokens_by_owner_in_collection.get(&key) {
+            token_ids.remove(token_id);
+            if token_ids.is_empty() {
+                self.tokens_by_owner_in_collection.remove(&key);
+                self.internal_update_collection_stats(collection_id, |stats| stats.holders = stats.holders.saturating_sub(1));
+            } else {
+                self.tokens_by_owner_in_collection.insert(&key, &token_ids);
+            }
+        }
+    }
+
+    /// Moves the token between owners in the index of its collection, if it has one.
+    /// Reverted transfers pass `is_transfer == false` so they aren't counted twice.
+    pub(crate) fn internal_on_token_moved(&mut self,
+                                          token_id: &TokenId,
+                                          old_owner_id: &AccountId,
+                                          new_owner_id: &AccountId,
+                                          is_transfer: bool) {
+        if old_owner_id == new_owner_id {
+            return;
+        }
+        if let Some(collection_id) = self.internal_token_collection_id(token_id) {
+            self.internal_remove_token_from_owner_in_collection(old_owner_id, &collection_id, token_id);
+            self.internal_add_token_to_owner_in_collection(new_owner_id, &collection_id, token_id);
+            self.internal_update_collection_stats(&collection_id, |stats| if is_transfer {
+                stats.transfers += 1
+            } else {
+                stats.transfers = stats.transfers.saturating_sub(1)
+            });
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use near_sdk::json_types::U128;
-use crate::Payout;
+use crate::*;
 use near_sdk::AccountId;
 use near_contract_standards::non_fungible_token::TokenId;
 
@@ -20,4 +20,40 @@ pub trait Payouts {
         balance: U128,
         max_len_payout: u32,
     ) -> Payout;
-}
\ No newline at end of file
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PayoutAmount {
+    pub amount: U128,
+    /// Fungible token contract of the amount, `None` for yoctoNEAR.
+    pub currency: Option<AccountId>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PayoutV2 {
+    pub payout: HashMap<AccountId, PayoutAmount>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// `nft_payout` of `balance` denominated in `currency`, the split doesn't depend
+    /// on the currency.
+    pub fn nft_payout_v2(&self,
+                         token_id: TokenId,
+                         balance: U128,
+                         max_len_payout: u32,
+                         currency: Option<AccountId>) -> PayoutV2 {
+        let payout = self
+            .nft_payout(token_id, balance, max_len_payout)
+            .payout
+            .into_iter()
+            .map(|(account_id, amount)| (account_id, PayoutAmount {
+                amount,
+                currency: currency.clone(),
+            }))
+            .collect();
+        PayoutV2 { payout }
+    }
+}
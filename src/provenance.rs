@@ -0,0 +1,24 @@
+use crate::*;
+use near_sdk::json_types::U64;
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenProvenance {
+    pub token_id: TokenId,
+    pub creator_id: AccountId,
+    /// Block timestamp of the mint in nanoseconds, unknown for tokens minted before it was recorded.
+    pub minted_at: Option<U64>,
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn nft_token_provenance(&self, token_id: TokenId) -> Option<TokenProvenance> {
+        self
+            .internal_token_creator(&token_id)
+            .map(|creator_id| TokenProvenance {
+                token_id: token_id.clone(),
+                creator_id,
+                minted_at: self.token_minted_at.get(&token_id).map(U64),
+            })
+    }
+}
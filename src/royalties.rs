@@ -0,0 +1,110 @@
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Replaces royalties of the token. Only the account which minted the token can call it.
+    /// Extra storage is paid from the attached deposit.
+    #[payable]
+    pub fn update_token_royalties(&mut self, token_id: TokenId, payout: Payout) {
+        assert_at_least_one_yocto();
+        payout.assert_valid();
+        let creator_id = env::predecessor_account_id();
+        self.assert_token_creator(&token_id, &creator_id);
+        let initial_storage_usage = env::storage_usage();
+        self.payouts.insert(&token_id, &payout);
+        refund_storage_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+
+        env::log_str(&json!({
+            "type": "update_royalties",
+            "data": {
+                "token_ids": [token_id],
+                "payout": payout
+            }
+        }).to_string());
+    }
+
+    /// Replaces royalties of the collection tokens in range `[from, from + limit)`,
+    /// so big collections can be updated in several transactions. Extra storage is
+    /// paid from the attached deposit.
+    #[payable]
+    pub fn update_collection_royalties(&mut self,
+                                       collection_id: CollectionId,
+                                       payout: Payout,
+                                       from: u64,
+                                       limit: u64) -> u64 {
+        assert_at_least_one_yocto();
+        payout.assert_valid();
+        self.assert_collection_tokens_not_rebuilding();
+        let creator_id = env::predecessor_account_id();
+        let collection = self
+            .collections
+            .get(&collection_id)
+            .expect("Error: no such collection id.");
+        assert_eq!(collection.owner_id,
+                   creator_id,
+                   "Only owner of collection can update its royalties");
+
+        let token_ids: Vec<TokenId> = self
+            .tokens_by_collection_id
+            .get(&collection_id)
+            .map(|tokens| tokens
+                .iter()
+                .skip(from as usize)
+                .take(limit as usize)
+                .collect())
+            .unwrap_or_default();
+
+        let initial_storage_usage = env::storage_usage();
+        for token_id in token_ids.iter() {
+            // burned ids stay in the collection vector
+            if self.tokens.owner_by_id.get(token_id).is_none() {
+                continue;
+            }
+            self.assert_token_creator(token_id, &creator_id);
+            self.payouts.insert(token_id, &payout);
+        }
+        refund_storage_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+
+        env::log_str(&json!({
+            "type": "update_royalties",
+            "data": {
+                "collection_id": collection_id,
+                "token_ids": token_ids,
+                "payout": payout
+            }
+        }).to_string());
+
+        token_ids.len() as u64
+    }
+
+    fn assert_token_creator(&self, token_id: &TokenId, account_id: &AccountId) {
+        let creator_id = self
+            .internal_token_creator(token_id)
+            .expect("Error: token creator is unknown.");
+        assert_eq!(&creator_id, account_id, "Only creator of token can update its royalties");
+    }
+
+    /// Minter of the token. Tokens minted before creators were recorded fall back
+    /// to the owner of their collection.
+    pub(crate) fn internal_token_creator(&self, token_id: &TokenId) -> Option<AccountId> {
+        self
+            .token_creators
+            .get(token_id)
+            .or_else(|| self
+                .internal_token_collection_id(token_id)
+                .and_then(|collection_id| self.collections.get(&collection_id))
+                .map(|collection| collection.owner_id))
+    }
+
+    /// Collection of the token, as written to `extra` of the token metadata on mint.
+    pub(crate) fn internal_token_collection_id(&self, token_id: &TokenId) -> Option<CollectionId> {
+        self
+            .tokens
+            .token_metadata_by_id
+            .as_ref()
+            .and_then(|by_id| by_id.get(token_id))
+            .and_then(|metadata| metadata.extra)
+            .and_then(|extra| near_sdk::serde_json::from_str::<near_sdk::serde_json::Value>(&extra).ok())
+            .and_then(|extra| extra["collection_id"].as_str().map(|id| id.to_string()))
+    }
+}
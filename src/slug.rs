@@ -0,0 +1,40 @@
+use crate::{COLLECTION_TAG, DELIMITER, TOKEN_TAG};
+
+const MIN_SLUG_LEN: usize = 3;
+const MAX_SLUG_LEN: usize = 64;
+const MAX_TOKEN_ID_LEN: usize = 64;
+
+/// Prefixes of auto-generated ids, custom ids can't start with them.
+fn reserved_prefixes() -> Vec<String> {
+    vec![
+        format!("{}{}", COLLECTION_TAG, DELIMITER),
+        format!("{}{}", TOKEN_TAG, DELIMITER),
+    ]
+}
+
+/// Checks custom collection id: lowercase latin letters, digits and inner hyphens.
+pub fn assert_valid_collection_slug(slug: &str) {
+    assert!(slug.len() >= MIN_SLUG_LEN && slug.len() <= MAX_SLUG_LEN,
+            "Collection id must be from {} to {} characters long, got {}",
+            MIN_SLUG_LEN, MAX_SLUG_LEN, slug.len());
+    assert!(slug.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-'),
+            "Collection id can contain only lowercase latin letters, digits and hyphens: {}", slug);
+    assert!(!slug.starts_with('-') && !slug.ends_with('-'),
+            "Collection id can't start or end with a hyphen: {}", slug);
+    assert_no_reserved_prefix(slug);
+}
+
+/// Checks custom token id: latin letters, digits, hyphens, underscores and dots.
+pub fn assert_valid_token_id(token_id: &str) {
+    assert!(!token_id.is_empty() && token_id.len() <= MAX_TOKEN_ID_LEN,
+            "Token id must be from 1 to {} characters long, got {}", MAX_TOKEN_ID_LEN, token_id.len());
+    assert!(token_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.'),
+            "Token id can contain only latin letters, digits, hyphens, underscores and dots: {}", token_id);
+    assert_no_reserved_prefix(token_id);
+}
+
+fn assert_no_reserved_prefix(id: &str) {
+    for prefix in reserved_prefixes() {
+        assert!(!id.starts_with(&prefix), "Id can't start with reserved prefix {}: {}", prefix, id);
+    }
+}
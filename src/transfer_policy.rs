@@ -0,0 +1,51 @@
+use crate::*;
+use near_sdk::json_types::U64;
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone, Copy, PartialEq, Default)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum TransferPolicy {
+    #[default]
+    Transferable,
+    /// Tokens stay with the account they were minted to, only the issuer can burn them.
+    Soulbound,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Time in nanoseconds until which the token can't be transferred, the later
+    /// of its own lock and the lock of its collection.
+    pub fn nft_token_locked_until(&self, token_id: TokenId) -> Option<U64> {
+        self.internal_token_locked_until(&token_id).map(U64)
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_token_locked_until(&self, token_id: &TokenId) -> Option<u64> {
+        let collection_lock = self
+            .internal_token_collection_id(token_id)
+            .and_then(|collection_id| self.collection_locked_until.get(&collection_id));
+        match (self.token_locked_until.get(token_id), collection_lock) {
+            (Some(token_lock), Some(collection_lock)) => Some(max(token_lock, collection_lock)),
+            (token_lock, collection_lock) => token_lock.or(collection_lock),
+        }
+    }
+
+    pub(crate) fn assert_token_unlocked(&self, token_id: &TokenId) {
+        if let Some(locked_until) = self.internal_token_locked_until(token_id) {
+            assert!(env::block_timestamp() >= locked_until,
+                    "Token {} is locked until {}", token_id, locked_until);
+        }
+    }
+
+    pub(crate) fn internal_collection_transfer_policy(&self, collection_id: &CollectionId) -> TransferPolicy {
+        self.collection_transfer_policy.get(collection_id).unwrap_or_default()
+    }
+
+    pub(crate) fn assert_token_transferable(&self, token_id: &TokenId) {
+        if let Some(collection_id) = self.internal_token_collection_id(token_id) {
+            assert!(self.internal_collection_transfer_policy(&collection_id) != TransferPolicy::Soulbound,
+                    "Tokens of collection {} can't be transferred", collection_id);
+        }
+    }
+}